@@ -0,0 +1,64 @@
+use crate::parser::alias::is_valid_name;
+
+pub struct Alias;
+
+impl Alias {
+    /// A definition that can be run again to recreate the alias.
+    fn definition(name: &str, value: &str) -> String {
+        format!("alias {}='{}'", name, value.replace('\'', r"'\''"))
+    }
+}
+
+impl<W: std::io::Write> super::Runnable<W> for Alias {
+    fn run(
+        &self,
+        args: Vec<String>,
+        out_writer: &mut W,
+        err_writer: &mut W,
+        shell: &mut crate::shell::Shell,
+    ) -> std::io::Result<i32> {
+        let mut args = &args[1..];
+
+        let mut print = false;
+        while let Some(flag) = args.first().filter(|arg| arg.starts_with('-')) {
+            match flag.as_str() {
+                "-p" => print = true,
+                "--" => {
+                    args = &args[1..];
+                    break;
+                }
+                _ => {
+                    writeln!(err_writer, "alias: {}: invalid option", flag)?;
+                    writeln!(err_writer, "alias: usage: alias [-p] [name[=value] ... ]")?;
+                    return Ok(2);
+                }
+            }
+            args = &args[1..];
+        }
+
+        if args.is_empty() || print {
+            for (name, value) in shell.aliases() {
+                writeln!(out_writer, "{}", Self::definition(name, value))?;
+            }
+        }
+
+        let mut status = 0;
+        for arg in args {
+            match arg.split_once('=') {
+                Some((name, value)) if is_valid_name(name) => shell.set_alias(name, value.into()),
+                Some((name, _)) => {
+                    writeln!(err_writer, "alias: `{}': invalid alias name", name)?;
+                    status = 1;
+                }
+                None => match shell.alias(arg) {
+                    Some(value) => writeln!(out_writer, "{}", Self::definition(arg, value))?,
+                    None => {
+                        writeln!(err_writer, "alias: {}: not found", arg)?;
+                        status = 1;
+                    }
+                },
+            }
+        }
+        Ok(status)
+    }
+}
@@ -0,0 +1,48 @@
+use crate::job::Status;
+
+pub struct Bg;
+
+impl<W: std::io::Write> super::Runnable<W> for Bg {
+    fn run(
+        &self,
+        args: Vec<String>,
+        out_writer: &mut W,
+        err_writer: &mut W,
+        shell: &mut crate::shell::Shell,
+    ) -> std::io::Result<i32> {
+        let jobs = shell.jobs_mut();
+        if !jobs.enabled() {
+            writeln!(err_writer, "bg: no job control")?;
+            return Ok(1);
+        }
+
+        let specs: Vec<Option<&str>> = if args.len() > 1 {
+            args[1..].iter().map(|spec| Some(spec.as_str())).collect()
+        } else {
+            vec![None]
+        };
+
+        let mut status = 0;
+        for spec in specs {
+            let id = match jobs.resolve(spec) {
+                Ok(id) => id,
+                Err(e) => {
+                    writeln!(err_writer, "bg: {}", e)?;
+                    status = 1;
+                    continue;
+                }
+            };
+            let Some(job) = jobs.get(id) else {
+                continue;
+            };
+            if job.status() == Status::Running {
+                writeln!(err_writer, "bg: job {} already in background", id)?;
+                continue;
+            }
+
+            writeln!(out_writer, "[{}]{} {} &", id, jobs.mark(id), job.command)?;
+            jobs.background(id);
+        }
+        Ok(status)
+    }
+}
@@ -1,28 +1,58 @@
-use std::io::Write;
+use std::os::unix::process::CommandExt;
 use std::process::{Command, Stdio};
 
-pub struct Binary(std::path::PathBuf);
+use crate::job::{self, Group};
+use crate::pipeline::redirect::Redirections;
+
+/// An executable found on disk, with the `NAME=value` assignments that
+/// prefixed it on the command line.
+pub struct Binary(std::path::PathBuf, Vec<(String, String)>);
 
 impl Binary {
     pub fn new(command: std::path::PathBuf) -> Self {
-        Self(command)
+        Self(command, Vec::new())
+    }
+
+    /// Adds variables to the environment of this command only.
+    pub fn with_env(mut self, env: Vec<(String, String)>) -> Self {
+        self.1 = env;
+        self
     }
 
     pub fn get_path(&self) -> &std::path::PathBuf {
         &self.0
     }
 
-    fn is_long_running_command(&self, args: &[String]) -> bool {
-        let command_name = self
-            .get_path()
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("");
-
-        let long_running_commands = ["watch", "top", "htop", "less", "more", "vi", "vim", "nano"];
+    /// Builds the command for the resolved path, keeping the name it was
+    /// invoked by as `argv[0]`.
+    fn command(&self, args: &[String]) -> Command {
+        let mut cmd = Command::new(self.get_path());
+        cmd.arg0(&args[0])
+            .args(&args[1..])
+            .envs(self.1.iter().cloned());
+        cmd
+    }
 
-        long_running_commands.contains(&command_name)
-            || args.iter().any(|arg| arg == "-f" || arg == "--follow")
+    /// Starts the binary without waiting for it, wiring the given standard
+    /// streams straight into the child so a pipeline can stream between stages.
+    /// `redirections` are applied on top of them, and with job control the
+    /// child joins the process `group`.
+    pub fn spawn(
+        &self,
+        args: &[String],
+        stdin: Stdio,
+        stdout: Stdio,
+        stderr: Stdio,
+        redirections: &Redirections,
+        group: Option<Group>,
+    ) -> std::io::Result<std::process::Child> {
+        if self.get_path().is_dir() {
+            return Err(std::io::Error::from_raw_os_error(libc::EISDIR));
+        }
+        let mut command = self.command(args);
+        unsafe { command.pre_exec(move || job::prepare_child(group)) };
+        redirections.install(&mut command);
+        command.stdin(stdin).stdout(stdout).stderr(stderr).spawn()
     }
 }
 
@@ -30,74 +60,20 @@ impl<W: std::io::Write> super::Runnable<W> for Binary {
     fn run(
         &self,
         args: Vec<String>,
-        input: Option<&mut dyn std::io::Read>,
-        out_writer: &mut W,
-        err_writer: &mut W,
-        history: &mut Vec<String>,
-    ) -> std::io::Result<()> {
-        history.push(args.join(" "));
-
-        let mut piped_input = String::new();
-        if let Some(input) = input {
-            input.read_to_string(&mut piped_input)?;
-        }
-
-        if self.is_long_running_command(&args) {
-            let mut cmd = Command::new(
-                self.get_path()
-                    .file_name()
-                    .expect("should return file name"),
-            );
-
-            cmd.args(&args[1..]);
-
-            if !piped_input.is_empty() {
-                cmd.stdin(Stdio::piped());
-            } else {
-                cmd.stdin(Stdio::inherit());
-            }
-
-            cmd.stdout(Stdio::inherit()).stderr(Stdio::inherit());
-
-            let mut child = cmd.spawn()?;
-
-            let _ = child.wait()?;
-
-            return Ok(());
-        }
-
-        if piped_input.is_empty() {
-            let output = Command::new(
-                self.get_path()
-                    .file_name()
-                    .expect("should return file name"),
-            )
-            .args(&args[1..])
-            .output()?;
-
-            out_writer.write_all(&output.stdout)?;
-            err_writer.write_all(&output.stderr)?;
-        } else {
-            let mut child = Command::new(
-                self.get_path()
-                    .file_name()
-                    .expect("should return file name"),
-            )
-            .args(&args[1..])
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()?;
-
-            if let Some(mut stdin) = child.stdin.take() {
-                stdin.write_all(piped_input.as_bytes())?;
-            }
-
-            let output = child.wait_with_output()?;
-            out_writer.write_all(&output.stdout)?;
-            err_writer.write_all(&output.stderr)?;
-        }
-
-        Ok(())
+        _out_writer: &mut W,
+        _err_writer: &mut W,
+        _shell: &mut crate::shell::Shell,
+    ) -> std::io::Result<i32> {
+        // The program shares the shell's own descriptors, so it sees the
+        // terminal exactly as the shell does.
+        let mut child = self.spawn(
+            &args,
+            Stdio::inherit(),
+            Stdio::inherit(),
+            Stdio::inherit(),
+            &Redirections::default(),
+            None,
+        )?;
+        Ok(super::exit_code(child.wait()?))
     }
 }
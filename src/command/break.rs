@@ -0,0 +1,43 @@
+use crate::shell::Flow;
+
+pub struct Break;
+
+impl<W: std::io::Write> super::Runnable<W> for Break {
+    fn run(
+        &self,
+        args: Vec<String>,
+        _out_writer: &mut W,
+        err_writer: &mut W,
+        shell: &mut crate::shell::Shell,
+    ) -> std::io::Result<i32> {
+        let args = &args[1..];
+
+        if shell.loops() == 0 {
+            writeln!(
+                err_writer,
+                "break: only meaningful in a `for', `while', or `until' loop"
+            )?;
+            return Ok(0);
+        }
+
+        // A bad count leaves every loop, as there is no telling how many were
+        // meant.
+        let count = match args.first().map(|arg| arg.parse::<i64>()) {
+            None => 1,
+            Some(Ok(n)) if n >= 1 => n as usize,
+            Some(Ok(n)) => {
+                writeln!(err_writer, "break: {}: loop count out of range", n)?;
+                shell.set_flow(Flow::Break(shell.loops()));
+                return Ok(1);
+            }
+            Some(Err(_)) => {
+                writeln!(err_writer, "break: {}: numeric argument required", args[0])?;
+                shell.set_flow(Flow::Break(shell.loops()));
+                return Ok(1);
+            }
+        };
+        // Asking for more loops than there are just leaves all of them.
+        shell.set_flow(Flow::Break(count.min(shell.loops())));
+        Ok(0)
+    }
+}
@@ -12,20 +12,13 @@ impl<W: std::io::Write> super::Runnable<W> for Cd {
     fn run(
         &self,
         args: Vec<String>,
-        _input: Option<&mut dyn std::io::Read>,
         _out_writer: &mut W,
         err_writer: &mut W,
-        history: &mut Vec<String>,
-    ) -> std::io::Result<()> {
-        history.push(args.join(" "));
-        let path = if let Some(path) = args.get(1) {
-            if path == "~" {
-                self.get_home_dir()
-            } else {
-                std::path::PathBuf::from(path)
-            }
-        } else {
-            self.get_home_dir()
+        shell: &mut crate::shell::Shell,
+    ) -> std::io::Result<i32> {
+        let path = match args.get(1) {
+            Some(path) => std::path::PathBuf::from(path),
+            None => self.get_home_dir(),
         };
 
         if std::env::set_current_dir(&path).is_err() {
@@ -33,8 +26,17 @@ impl<W: std::io::Write> super::Runnable<W> for Cd {
                 err_writer,
                 "cd: {}: No such file or directory",
                 path.display(),
-            )?
+            )?;
+            return Ok(1);
         }
-        Ok(())
+
+        // Keep `~+` and `~-` in sync with the working directory.
+        if let Some(old) = shell.get_var("PWD") {
+            shell.set_var("OLDPWD", old);
+        }
+        if let Ok(cwd) = std::env::current_dir() {
+            shell.set_var("PWD", cwd.display().to_string());
+        }
+        Ok(0)
     }
 }
@@ -0,0 +1,82 @@
+pub struct Export;
+
+impl<W: std::io::Write> super::Runnable<W> for Export {
+    fn run(
+        &self,
+        args: Vec<String>,
+        out_writer: &mut W,
+        err_writer: &mut W,
+        shell: &mut crate::shell::Shell,
+    ) -> std::io::Result<i32> {
+        let mut args = &args[1..];
+
+        let mut unexport = false;
+        while let Some(flag) = args.first().filter(|arg| arg.starts_with('-')) {
+            match flag.as_str() {
+                "-n" => unexport = true,
+                "-p" => {}
+                "--" => {
+                    args = &args[1..];
+                    break;
+                }
+                _ => {
+                    writeln!(err_writer, "export: {}: invalid option", flag)?;
+                    writeln!(
+                        err_writer,
+                        "export: usage: export [-n] [name[=value] ...] or export -p"
+                    )?;
+                    return Ok(2);
+                }
+            }
+            args = &args[1..];
+        }
+
+        if args.is_empty() {
+            for (name, var) in shell.exported() {
+                match &var.value {
+                    Some(value) => {
+                        writeln!(out_writer, "declare -x {}=\"{}\"", name, escape(value))?
+                    }
+                    None => writeln!(out_writer, "declare -x {}", name)?,
+                }
+            }
+            return Ok(0);
+        }
+
+        let mut status = 0;
+        for arg in args {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+            if !crate::parser::expand::is_name(name) {
+                writeln!(err_writer, "export: `{}': not a valid identifier", arg)?;
+                status = 1;
+                continue;
+            }
+
+            if unexport {
+                if let Some(value) = value {
+                    shell.set_var(name, value);
+                }
+                shell.unexport(name);
+            } else {
+                shell.export(name, value);
+            }
+        }
+        Ok(status)
+    }
+}
+
+/// Escapes a value for use inside double quotes, so that the listing can be
+/// read back by the shell.
+pub(super) fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        if matches!(ch, '"' | '\\' | '$' | '`') {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
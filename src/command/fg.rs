@@ -0,0 +1,31 @@
+pub struct Fg;
+
+impl<W: std::io::Write> super::Runnable<W> for Fg {
+    fn run(
+        &self,
+        args: Vec<String>,
+        out_writer: &mut W,
+        err_writer: &mut W,
+        shell: &mut crate::shell::Shell,
+    ) -> std::io::Result<i32> {
+        let jobs = shell.jobs_mut();
+        if !jobs.enabled() {
+            writeln!(err_writer, "fg: no job control")?;
+            return Ok(1);
+        }
+        let id = match jobs.resolve(args.get(1).map(String::as_str)) {
+            Ok(id) => id,
+            Err(e) => {
+                writeln!(err_writer, "fg: {}", e)?;
+                return Ok(1);
+            }
+        };
+
+        if let Some(job) = jobs.get(id) {
+            writeln!(out_writer, "{}", job.command)?;
+            out_writer.flush()?;
+        }
+        let statuses = jobs.foreground(id)?;
+        Ok(statuses.last().copied().unwrap_or(0))
+    }
+}
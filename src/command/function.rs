@@ -0,0 +1,29 @@
+use std::rc::Rc;
+
+use crate::parser::ast;
+
+/// A shell function, run in the current shell with its own positional
+/// parameters.
+pub struct Function(Rc<ast::Command>);
+
+impl Function {
+    pub fn new(body: Rc<ast::Command>) -> Self {
+        Self(body)
+    }
+
+    pub fn body(&self) -> Rc<ast::Command> {
+        self.0.clone()
+    }
+}
+
+impl<W: std::io::Write> super::Runnable<W> for Function {
+    fn run(
+        &self,
+        args: Vec<String>,
+        _out_writer: &mut W,
+        _err_writer: &mut W,
+        shell: &mut crate::shell::Shell,
+    ) -> std::io::Result<i32> {
+        shell.call_function(&self.0, args)
+    }
+}
@@ -0,0 +1,126 @@
+pub struct Hash;
+
+impl<W: std::io::Write> super::Runnable<W> for Hash {
+    fn run(
+        &self,
+        args: Vec<String>,
+        out_writer: &mut W,
+        err_writer: &mut W,
+        shell: &mut crate::shell::Shell,
+    ) -> std::io::Result<i32> {
+        let mut args = &args[1..];
+
+        let (mut reset, mut list, mut delete, mut show) = (false, false, false, false);
+        let mut path = None;
+        while let Some(flags) = args.first().filter(|arg| arg.starts_with('-')) {
+            args = &args[1..];
+            for flag in flags[1..].chars() {
+                match flag {
+                    'r' => reset = true,
+                    'l' => list = true,
+                    'd' => delete = true,
+                    't' => show = true,
+                    'p' => match args.split_first() {
+                        Some((first, rest)) => {
+                            path = Some(std::path::PathBuf::from(first));
+                            args = rest;
+                        }
+                        None => {
+                            writeln!(err_writer, "hash: -p: option requires an argument")?;
+                            return Ok(1);
+                        }
+                    },
+                    _ => {
+                        writeln!(err_writer, "hash: -{}: invalid option", flag)?;
+                        writeln!(
+                            err_writer,
+                            "hash: usage: hash [-lr] [-p pathname] [-dt] [name ...]"
+                        )?;
+                        return Ok(2);
+                    }
+                }
+            }
+        }
+
+        if reset {
+            shell.clear_hash();
+        }
+
+        if args.is_empty() {
+            if show || path.is_some() {
+                let flag = if show { "-t" } else { "-p" };
+                writeln!(err_writer, "hash: {}: option requires an argument", flag)?;
+                return Ok(1);
+            }
+            if !reset && !delete {
+                list_table(out_writer, shell, list)?;
+            }
+            return Ok(0);
+        }
+
+        let mut status = 0;
+        for name in args {
+            if let Some(path) = &path {
+                shell.hash(name, path.clone());
+                continue;
+            }
+            let found = if delete {
+                shell.unhash(name)
+            } else if show {
+                match shell.hashed(name) {
+                    Some(hashed) if args.len() > 1 => {
+                        writeln!(out_writer, "{}\t{}", name, hashed.path.display())?;
+                        true
+                    }
+                    Some(hashed) => {
+                        writeln!(out_writer, "{}", hashed.path.display())?;
+                        true
+                    }
+                    None => false,
+                }
+            } else {
+                // Builtins and paths are never looked up, so they are not
+                // hashed either.
+                name.contains('/') || name.parse::<super::Builtin>().is_ok() || {
+                    shell.unhash(name);
+                    shell.find_program(name).is_some()
+                }
+            };
+            if !found {
+                writeln!(err_writer, "hash: {}: not found", name)?;
+                status = 1;
+            }
+        }
+        Ok(status)
+    }
+}
+
+/// Prints the table, with `-l` as commands that recreate it.
+fn list_table<W: std::io::Write>(
+    out_writer: &mut W,
+    shell: &crate::shell::Shell,
+    reusable: bool,
+) -> std::io::Result<()> {
+    let mut programs = shell.hashed_programs().peekable();
+    if programs.peek().is_none() {
+        writeln!(out_writer, "hash: hash table empty")?;
+        return Ok(());
+    }
+
+    if !reusable {
+        writeln!(out_writer, "hits\tcommand")?;
+    }
+    for (name, hashed) in programs {
+        if reusable {
+            writeln!(
+                out_writer,
+                "builtin hash -p {} {}",
+                hashed.path.display(),
+                name
+            )?;
+        } else {
+            writeln!(out_writer, "{:>4}\t{}", hashed.hits, hashed.path.display())?;
+        }
+    }
+    Ok(())
+}
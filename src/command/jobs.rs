@@ -0,0 +1,68 @@
+pub struct Jobs;
+
+impl<W: std::io::Write> super::Runnable<W> for Jobs {
+    fn run(
+        &self,
+        args: Vec<String>,
+        out_writer: &mut W,
+        err_writer: &mut W,
+        shell: &mut crate::shell::Shell,
+    ) -> std::io::Result<i32> {
+        let mut args = &args[1..];
+
+        let (mut long, mut pids) = (false, false);
+        while let Some(flags) = args.first().filter(|arg| arg.starts_with('-')) {
+            for flag in flags[1..].chars() {
+                match flag {
+                    'l' => long = true,
+                    'p' => pids = true,
+                    _ => {
+                        writeln!(err_writer, "jobs: -{}: invalid option", flag)?;
+                        writeln!(err_writer, "jobs: usage: jobs [-lp] [jobspec ...]")?;
+                        return Ok(2);
+                    }
+                }
+            }
+            args = &args[1..];
+        }
+
+        let jobs = shell.jobs_mut();
+        jobs.poll()?;
+
+        let mut status = 0;
+        let ids: Vec<usize> = if args.is_empty() {
+            jobs.iter().map(|job| job.id).collect()
+        } else {
+            let mut ids = Vec::new();
+            for spec in args {
+                match jobs.resolve(Some(spec)) {
+                    Ok(id) => ids.push(id),
+                    Err(e) => {
+                        writeln!(err_writer, "jobs: {}", e)?;
+                        status = 1;
+                    }
+                }
+            }
+            ids
+        };
+
+        for id in ids {
+            if pids {
+                if let Some(job) = jobs.get(id) {
+                    let pid = if job.pgid != 0 {
+                        job.pgid
+                    } else {
+                        job.last_pid()
+                    };
+                    writeln!(out_writer, "{}", pid)?;
+                }
+            } else {
+                jobs.print(id, long, out_writer)?;
+            }
+        }
+
+        // Finished jobs are reported once, here or before the next prompt.
+        jobs.remove_done();
+        Ok(status)
+    }
+}
@@ -0,0 +1,51 @@
+pub struct Local;
+
+impl<W: std::io::Write> super::Runnable<W> for Local {
+    fn run(
+        &self,
+        args: Vec<String>,
+        out_writer: &mut W,
+        err_writer: &mut W,
+        shell: &mut crate::shell::Shell,
+    ) -> std::io::Result<i32> {
+        let args = &args[1..];
+
+        if !shell.in_function() {
+            writeln!(err_writer, "local: can only be used in a function")?;
+            return Ok(1);
+        }
+
+        if args.is_empty() {
+            for (name, value) in shell.locals() {
+                match value {
+                    Some(value) => writeln!(
+                        out_writer,
+                        "declare -- {}=\"{}\"",
+                        name,
+                        super::export::escape(value)
+                    )?,
+                    None => writeln!(out_writer, "declare -- {}", name)?,
+                }
+            }
+            return Ok(0);
+        }
+
+        let mut status = 0;
+        for arg in args {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+            if !crate::parser::expand::is_name(name) {
+                writeln!(err_writer, "local: `{}': not a valid identifier", arg)?;
+                status = 1;
+                continue;
+            }
+            if let Err(e) = shell.make_local(name, value) {
+                writeln!(err_writer, "local: {}", e)?;
+                status = 1;
+            }
+        }
+        Ok(status)
+    }
+}
@@ -1,18 +1,52 @@
+mod alias;
+mod bg;
 mod binary;
+mod r#break;
 mod cd;
+mod r#continue;
 mod echo;
 mod exit;
+mod export;
+mod fg;
+mod function;
+mod hash;
 mod history;
+mod jobs;
+mod local;
 mod pwd;
+mod r#return;
+mod set;
+mod shopt;
+mod source;
 mod r#type;
+mod unalias;
+mod unset;
+mod wait;
 
+use alias::Alias;
+use bg::Bg;
 use binary::Binary;
 use cd::Cd;
 use echo::Echo;
 use exit::Exit;
+use export::Export;
+use fg::Fg;
+use function::Function;
+use hash::Hash;
 use history::History;
+use jobs::Jobs;
+use local::Local;
 use pwd::Pwd;
+use r#break::Break;
+use r#continue::Continue;
+use r#return::Return;
 use r#type::Type;
+use set::Set;
+use shopt::Shopt;
+use source::Source;
+use unalias::Unalias;
+use unset::Unset;
+use wait::Wait;
 
 pub trait Runnable<W>
 where
@@ -21,16 +55,16 @@ where
     fn run(
         &self,
         args: Vec<String>,
-        input: Option<&mut dyn std::io::Read>,
         out_writer: &mut W,
         err_writer: &mut W,
-        history: &mut Vec<String>,
-    ) -> std::io::Result<()>;
+        shell: &mut crate::shell::Shell,
+    ) -> std::io::Result<i32>;
 }
 
 pub enum Command {
     Builtin(Builtin),
     Binary(Binary),
+    Function(Function),
     Unknown(String),
 }
 
@@ -38,29 +72,71 @@ impl<W: std::io::Write> Runnable<W> for Command {
     fn run(
         &self,
         args: Vec<String>,
-        input: Option<&mut dyn std::io::Read>,
         out_writer: &mut W,
         err_writer: &mut W,
-        history: &mut Vec<String>,
-    ) -> std::io::Result<()> {
+        shell: &mut crate::shell::Shell,
+    ) -> std::io::Result<i32> {
         match self {
-            Command::Builtin(builtin) => builtin.run(args, input, out_writer, err_writer, history),
-            Command::Binary(binary) => binary.run(args, input, out_writer, err_writer, history),
+            Command::Builtin(builtin) => builtin.run(args, out_writer, err_writer, shell),
+            Command::Binary(binary) => binary.run(args, out_writer, err_writer, shell),
+            Command::Function(function) => function.run(args, out_writer, err_writer, shell),
             Command::Unknown(s) => {
-                history.push(s.to_string());
-                writeln!(err_writer, "{}: command not found", args[0])
+                if s.contains('/') {
+                    writeln!(err_writer, "{}: No such file or directory", args[0])?;
+                } else {
+                    writeln!(err_writer, "{}: command not found", args[0])?;
+                }
+                Ok(127)
             }
         }
     }
 }
 
+impl Command {
+    /// Applies prefix assignments like `FOO=1 cmd` to the command's
+    /// environment. Only external programs have an environment of their own.
+    pub fn with_env(self, env: Vec<(String, String)>) -> Self {
+        match self {
+            Command::Binary(binary) => Command::Binary(binary.with_env(env)),
+            cmd => cmd,
+        }
+    }
+}
+
+impl Command {
+    /// Resolves a command name like `from_str`, except that functions come
+    /// first and programs found in `PATH` go through the shell's hash table.
+    pub fn lookup(name: &str, shell: &mut crate::shell::Shell) -> Self {
+        if let Some(body) = shell.function(name) {
+            return Command::Function(Function::new(body));
+        }
+        if name.contains('/') || name.parse::<Builtin>().is_ok() {
+            return name
+                .parse()
+                .unwrap_or_else(|_| Command::Unknown(name.to_string()));
+        }
+        match shell.find_program(name) {
+            Some(path) => Command::Binary(Binary::new(path)),
+            None => Command::Unknown(name.to_string()),
+        }
+    }
+}
+
 impl std::str::FromStr for Command {
     type Err = ();
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         if s.parse::<Builtin>().is_ok() {
             Ok(Command::Builtin(s.parse().unwrap()))
-        } else if find_in_path(s).is_some() {
-            Ok(Command::Binary(Binary::new(find_in_path(s).unwrap())))
+        } else if s.contains('/') {
+            // A path is run as given, without searching `PATH`.
+            let path = std::path::PathBuf::from(s);
+            if path.exists() {
+                Ok(Command::Binary(Binary::new(path)))
+            } else {
+                Ok(Command::Unknown(s.to_string()))
+            }
+        } else if let Some(path) = find_in_path(s) {
+            Ok(Command::Binary(Binary::new(path)))
         } else {
             Ok(Command::Unknown(s.to_string()))
         }
@@ -73,25 +149,56 @@ pub enum Builtin {
     Type(Type),
     Pwd(Pwd),
     Cd(Cd),
+    Hash(Hash),
     History(History),
+    Export(Export),
+    Unset(Unset),
+    Shopt(Shopt),
+    Set(Set),
+    Jobs(Jobs),
+    Fg(Fg),
+    Bg(Bg),
+    Wait(Wait),
+    Local(Local),
+    Return(Return),
+    Break(Break),
+    Continue(Continue),
+    Source(Source),
+    Alias(Alias),
+    Unalias(Unalias),
 }
 
 impl<W: std::io::Write> Runnable<W> for Builtin {
     fn run(
         &self,
         args: Vec<String>,
-        input: Option<&mut dyn std::io::Read>,
         out_writer: &mut W,
         err_writer: &mut W,
-        history: &mut Vec<String>,
-    ) -> std::io::Result<()> {
+        shell: &mut crate::shell::Shell,
+    ) -> std::io::Result<i32> {
         match self {
-            Builtin::Echo(echo) => echo.run(args, input, out_writer, err_writer, history),
-            Builtin::Exit(exit) => exit.run(args, input, out_writer, err_writer, history),
-            Builtin::Type(ty) => ty.run(args, input, out_writer, err_writer, history),
-            Builtin::Pwd(pwd) => pwd.run(args, input, out_writer, err_writer, history),
-            Builtin::Cd(cd) => cd.run(args, input, out_writer, err_writer, history),
-            Builtin::History(hist) => hist.run(args, input, out_writer, err_writer, history),
+            Builtin::Echo(echo) => echo.run(args, out_writer, err_writer, shell),
+            Builtin::Exit(exit) => exit.run(args, out_writer, err_writer, shell),
+            Builtin::Type(ty) => ty.run(args, out_writer, err_writer, shell),
+            Builtin::Pwd(pwd) => pwd.run(args, out_writer, err_writer, shell),
+            Builtin::Cd(cd) => cd.run(args, out_writer, err_writer, shell),
+            Builtin::Hash(hash) => hash.run(args, out_writer, err_writer, shell),
+            Builtin::History(hist) => hist.run(args, out_writer, err_writer, shell),
+            Builtin::Export(export) => export.run(args, out_writer, err_writer, shell),
+            Builtin::Unset(unset) => unset.run(args, out_writer, err_writer, shell),
+            Builtin::Shopt(shopt) => shopt.run(args, out_writer, err_writer, shell),
+            Builtin::Set(set) => set.run(args, out_writer, err_writer, shell),
+            Builtin::Jobs(jobs) => jobs.run(args, out_writer, err_writer, shell),
+            Builtin::Fg(fg) => fg.run(args, out_writer, err_writer, shell),
+            Builtin::Bg(bg) => bg.run(args, out_writer, err_writer, shell),
+            Builtin::Wait(wait) => wait.run(args, out_writer, err_writer, shell),
+            Builtin::Local(local) => local.run(args, out_writer, err_writer, shell),
+            Builtin::Return(ret) => ret.run(args, out_writer, err_writer, shell),
+            Builtin::Break(brk) => brk.run(args, out_writer, err_writer, shell),
+            Builtin::Continue(cont) => cont.run(args, out_writer, err_writer, shell),
+            Builtin::Source(source) => source.run(args, out_writer, err_writer, shell),
+            Builtin::Alias(alias) => alias.run(args, out_writer, err_writer, shell),
+            Builtin::Unalias(unalias) => unalias.run(args, out_writer, err_writer, shell),
         }
     }
 }
@@ -106,21 +213,98 @@ impl std::str::FromStr for Builtin {
             "type" => Ok(Builtin::Type(Type)),
             "pwd" => Ok(Builtin::Pwd(Pwd)),
             "cd" => Ok(Builtin::Cd(Cd)),
+            "hash" => Ok(Builtin::Hash(Hash)),
             "history" => Ok(Builtin::History(History)),
+            "export" => Ok(Builtin::Export(Export)),
+            "unset" => Ok(Builtin::Unset(Unset)),
+            "shopt" => Ok(Builtin::Shopt(Shopt)),
+            "set" => Ok(Builtin::Set(Set)),
+            "jobs" => Ok(Builtin::Jobs(Jobs)),
+            "fg" => Ok(Builtin::Fg(Fg)),
+            "bg" => Ok(Builtin::Bg(Bg)),
+            "wait" => Ok(Builtin::Wait(Wait)),
+            "local" => Ok(Builtin::Local(Local)),
+            "return" => Ok(Builtin::Return(Return)),
+            "break" => Ok(Builtin::Break(Break)),
+            "continue" => Ok(Builtin::Continue(Continue)),
+            "source" | "." => Ok(Builtin::Source(Source)),
+            "alias" => Ok(Builtin::Alias(Alias)),
+            "unalias" => Ok(Builtin::Unalias(Unalias)),
             _ => Err(()),
         }
     }
 }
 
-fn find_in_path(command: &str) -> Option<std::path::PathBuf> {
-    std::env::var("PATH").ok()?.split(':').find_map(|dir| {
-        let full_path = std::path::Path::new(dir).join(command);
-        if is_executable(&full_path) {
-            Some(full_path)
-        } else {
-            None
+/// Finds `command` in `PATH`. If only a file without execute permission
+/// matches, that one is returned so running it reports why it failed.
+pub fn find_in_path(command: &str) -> Option<std::path::PathBuf> {
+    let candidates: Vec<_> = std::env::var("PATH")
+        .ok()?
+        .split(':')
+        .map(|dir| std::path::Path::new(dir).join(command))
+        .filter(|path| path.is_file())
+        .collect();
+    candidates
+        .iter()
+        .find(|path| is_executable(path))
+        .or(candidates.first())
+        .cloned()
+}
+
+/// Writes error messages with `prefix`, such as the script and line being
+/// run, at the start of every line.
+pub struct Prefixed<W> {
+    inner: W,
+    prefix: String,
+    at_line_start: bool,
+}
+
+impl<W: std::io::Write> Prefixed<W> {
+    pub fn new(inner: W, prefix: String) -> Self {
+        Prefixed {
+            inner,
+            prefix,
+            at_line_start: true,
+        }
+    }
+}
+
+impl<W: std::io::Write> std::io::Write for Prefixed<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for line in buf.split_inclusive(|&byte| byte == b'\n') {
+            if self.at_line_start {
+                self.inner.write_all(self.prefix.as_bytes())?;
+            }
+            self.inner.write_all(line)?;
+            self.at_line_start = line.ends_with(b"\n");
         }
-    })
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// The text of an I/O error as `strerror` gives it, without the `(os error
+/// N)` that Rust adds.
+pub fn error_text(e: &std::io::Error) -> String {
+    match e.raw_os_error() {
+        Some(code) => unsafe { std::ffi::CStr::from_ptr(libc::strerror(code)) }
+            .to_string_lossy()
+            .into_owned(),
+        None => e.to_string(),
+    }
+}
+
+/// Converts a child's wait status into a shell exit status, reporting death by
+/// signal as `128 + signal` like other shells do.
+pub fn exit_code(status: std::process::ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+
+    status
+        .code()
+        .unwrap_or_else(|| 128 + status.signal().unwrap_or(0))
 }
 
 fn is_executable(path: &std::path::Path) -> bool {
@@ -0,0 +1,37 @@
+use crate::shell::Flow;
+
+pub struct Return;
+
+impl<W: std::io::Write> super::Runnable<W> for Return {
+    fn run(
+        &self,
+        args: Vec<String>,
+        _out_writer: &mut W,
+        err_writer: &mut W,
+        shell: &mut crate::shell::Shell,
+    ) -> std::io::Result<i32> {
+        let args = &args[1..];
+
+        if !shell.can_return() {
+            writeln!(
+                err_writer,
+                "return: can only `return' from a function or sourced script"
+            )?;
+            return Ok(2);
+        }
+
+        let status = match args.first() {
+            None => shell.last_status(),
+            Some(arg) => match arg.parse::<i64>() {
+                // Like an exit status, the value is taken modulo 256.
+                Ok(n) => n.rem_euclid(256) as i32,
+                Err(_) => {
+                    writeln!(err_writer, "return: {}: numeric argument required", arg)?;
+                    2
+                }
+            },
+        };
+        shell.set_flow(Flow::Return);
+        Ok(status)
+    }
+}
@@ -0,0 +1,86 @@
+pub struct Set;
+
+impl<W: std::io::Write> super::Runnable<W> for Set {
+    fn run(
+        &self,
+        args: Vec<String>,
+        out_writer: &mut W,
+        err_writer: &mut W,
+        shell: &mut crate::shell::Shell,
+    ) -> std::io::Result<i32> {
+        if args.len() == 1 {
+            for (name, var) in shell.variables() {
+                if let Some(value) = &var.value {
+                    writeln!(out_writer, "{}={}", name, quote(value))?;
+                }
+            }
+            return Ok(0);
+        }
+
+        let mut status = 0;
+        let mut args = args[1..].iter();
+        while let Some(arg) = args.next() {
+            // The remaining arguments become the positional parameters.
+            if arg == "--" {
+                shell.set_positional(args.cloned().collect());
+                break;
+            }
+            let enable = arg.starts_with('-');
+            let Some(flags) = arg.strip_prefix(['-', '+']) else {
+                let positional = std::iter::once(arg).chain(args).cloned().collect();
+                shell.set_positional(positional);
+                break;
+            };
+
+            for flag in flags.chars() {
+                match flag {
+                    'C' => {
+                        shell.set_option("noclobber", enable);
+                    }
+                    'o' => match args.next() {
+                        Some(name) => {
+                            if !shell.set_option(name, enable) {
+                                writeln!(err_writer, "set: {}: invalid option name", name)?;
+                                status = 1;
+                            }
+                        }
+                        None => {
+                            // Without a name, list the options.
+                            for (name, enabled) in shell.options() {
+                                if enable {
+                                    let state = if enabled { "on" } else { "off" };
+                                    writeln!(out_writer, "{:<15}\t{}", name, state)?;
+                                } else {
+                                    let flag = if enabled { '-' } else { '+' };
+                                    writeln!(out_writer, "set {}o {}", flag, name)?;
+                                }
+                            }
+                        }
+                    },
+                    _ => {
+                        writeln!(err_writer, "set: {}{}: invalid option", &arg[..1], flag)?;
+                        writeln!(
+                            err_writer,
+                            "set: usage: set [-C] [-o option-name] [--] [arg ...]"
+                        )?;
+                        return Ok(2);
+                    }
+                }
+            }
+        }
+        Ok(status)
+    }
+}
+
+/// Quotes a value with single quotes when it contains anything but plain
+/// word characters, so that the listing can be read back by the shell.
+fn quote(value: &str) -> String {
+    let plain = value
+        .chars()
+        .all(|ch| ch.is_ascii_alphanumeric() || "_-./:,+@%".contains(ch));
+    if plain && !value.is_empty() {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
@@ -0,0 +1,107 @@
+pub struct Shopt;
+
+impl<W: std::io::Write> super::Runnable<W> for Shopt {
+    fn run(
+        &self,
+        args: Vec<String>,
+        out_writer: &mut W,
+        err_writer: &mut W,
+        shell: &mut crate::shell::Shell,
+    ) -> std::io::Result<i32> {
+        let mut args = &args[1..];
+
+        let (mut set, mut unset, mut print, mut quiet) = (false, false, false, false);
+        while let Some(flags) = args.first().filter(|arg| arg.starts_with('-')) {
+            for flag in flags[1..].chars() {
+                match flag {
+                    's' => set = true,
+                    'u' => unset = true,
+                    'p' => print = true,
+                    'q' => quiet = true,
+                    _ => {
+                        writeln!(err_writer, "shopt: -{}: invalid option", flag)?;
+                        writeln!(err_writer, "shopt: usage: shopt [-pqsu] [optname ...]")?;
+                        return Ok(2);
+                    }
+                }
+            }
+            args = &args[1..];
+        }
+
+        if set && unset {
+            writeln!(
+                err_writer,
+                "shopt: cannot set and unset shell options simultaneously"
+            )?;
+            return Ok(1);
+        }
+
+        let mut status = 0;
+        if set || unset {
+            for name in args {
+                if !shell.set_shopt(name, set) {
+                    writeln!(err_writer, "shopt: {}: invalid shell option name", name)?;
+                    status = 1;
+                }
+            }
+            if args.is_empty() {
+                // Without names, list the options that are in the given state.
+                for (name, enabled) in shell.shopts().filter(|(_, enabled)| *enabled == set) {
+                    print_option(out_writer, name, enabled, print)?;
+                }
+            }
+            return Ok(status);
+        }
+
+        let options: Vec<_> = if args.is_empty() {
+            shell.shopts().collect()
+        } else {
+            let mut options = Vec::new();
+            for name in args {
+                match shell.shopts().find(|(option, _)| option == name) {
+                    Some(option) => options.push(option),
+                    None => {
+                        writeln!(err_writer, "shopt: {}: invalid shell option name", name)?;
+                        status = 1;
+                    }
+                }
+            }
+            options
+        };
+
+        for (name, enabled) in options {
+            if !enabled {
+                status = status.max(1);
+            }
+            if !quiet {
+                print_option(out_writer, name, enabled, print)?;
+            }
+        }
+        Ok(status)
+    }
+}
+
+/// Prints an option either as a table row or, with `-p`, as the command that
+/// restores it.
+fn print_option<W: std::io::Write>(
+    out_writer: &mut W,
+    name: &str,
+    enabled: bool,
+    reusable: bool,
+) -> std::io::Result<()> {
+    if reusable {
+        writeln!(
+            out_writer,
+            "shopt {} {}",
+            if enabled { "-s" } else { "-u" },
+            name
+        )
+    } else {
+        writeln!(
+            out_writer,
+            "{:<15}\t{}",
+            name,
+            if enabled { "on" } else { "off" }
+        )
+    }
+}
@@ -0,0 +1,71 @@
+pub struct Source;
+
+impl Source {
+    /// Where to read `name` from: a name with a slash is used as given, any
+    /// other is looked for in `PATH` and then in the current directory.
+    fn find(name: &str) -> std::path::PathBuf {
+        if !name.contains('/') {
+            let found = std::env::var("PATH").ok().and_then(|paths| {
+                paths
+                    .split(':')
+                    .map(|dir| std::path::Path::new(dir).join(name))
+                    .find(|path| path.is_file())
+            });
+            if let Some(path) = found {
+                return path;
+            }
+        }
+        std::path::PathBuf::from(name)
+    }
+}
+
+impl<W: std::io::Write> super::Runnable<W> for Source {
+    fn run(
+        &self,
+        args: Vec<String>,
+        _out_writer: &mut W,
+        err_writer: &mut W,
+        shell: &mut crate::shell::Shell,
+    ) -> std::io::Result<i32> {
+        let builtin = &args[0];
+
+        let Some(name) = args.get(1) else {
+            writeln!(err_writer, "{}: filename argument required", builtin)?;
+            writeln!(
+                err_writer,
+                "{}: usage: {} filename [arguments]",
+                builtin, builtin
+            )?;
+            return Ok(2);
+        };
+
+        let path = Self::find(name);
+        if path.is_dir() {
+            writeln!(err_writer, "{}: {}: is a directory", builtin, name)?;
+            return Ok(1);
+        }
+        let text = match std::fs::read(&path) {
+            Ok(text) => String::from_utf8_lossy(&text).into_owned(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                writeln!(
+                    err_writer,
+                    "{}: {}: No such file or directory",
+                    builtin, name
+                )?;
+                return Ok(1);
+            }
+            Err(e) => {
+                writeln!(
+                    err_writer,
+                    "{}: {}: {}",
+                    builtin,
+                    name,
+                    super::error_text(&e)
+                )?;
+                return Ok(1);
+            }
+        };
+
+        shell.source(name, &text, args[2..].to_vec())
+    }
+}
@@ -4,30 +4,62 @@ impl<W: std::io::Write> super::Runnable<W> for Type {
     fn run(
         &self,
         args: Vec<String>,
-        _input: Option<&mut dyn std::io::Read>,
         out_writer: &mut W,
         err_writer: &mut W,
-        history: &mut Vec<String>,
-    ) -> std::io::Result<()> {
-        history.push(args.join(" "));
+        shell: &mut crate::shell::Shell,
+    ) -> std::io::Result<i32> {
         let args = &args[1..];
 
         if let Some(arg) = args.first() {
-            match arg.parse::<super::Command>() {
+            if let Some(value) = shell.alias(arg) {
+                writeln!(out_writer, "{} is aliased to `{}'", arg, value)?;
+                return Ok(0);
+            }
+            let command = match shell.function(arg) {
+                Some(body) => Ok(super::Command::Function(super::Function::new(body))),
+                // Like running it, the hash table comes before `PATH`.
+                None => match shell.hashed(arg) {
+                    Some(hashed)
+                        if !arg.contains('/') && arg.parse::<super::Builtin>().is_err() =>
+                    {
+                        Ok(super::Command::Binary(super::Binary::new(
+                            hashed.path.clone(),
+                        )))
+                    }
+                    _ => arg.parse::<super::Command>(),
+                },
+            };
+            match command {
                 Ok(super::Command::Builtin(_)) => {
-                    out_writer.write_all(format!("{} is a shell builtin\n", arg).as_bytes())
+                    out_writer.write_all(format!("{} is a shell builtin\n", arg).as_bytes())?;
+                    Ok(0)
+                }
+                Ok(super::Command::Binary(path)) => {
+                    let line = match shell.hashed(arg) {
+                        Some(hashed) => format!("{} is hashed ({})\n", arg, hashed.path.display()),
+                        None => format!("{} is {}\n", arg, path.get_path().display()),
+                    };
+                    out_writer.write_all(line.as_bytes())?;
+                    Ok(0)
+                }
+                Ok(super::Command::Function(function)) => {
+                    let definition =
+                        crate::parser::ast::Command::Function(arg.clone(), function.body());
+                    writeln!(out_writer, "{} is a function\n{:#}", arg, definition)?;
+                    Ok(0)
                 }
-                Ok(super::Command::Binary(path)) => out_writer
-                    .write_all(format!("{} is {}\n", arg, path.get_path().display()).as_bytes()),
                 Ok(super::Command::Unknown(_)) => {
-                    out_writer.write_all(format!("{}: not found\n", arg).as_bytes())
+                    out_writer.write_all(format!("{}: not found\n", arg).as_bytes())?;
+                    Ok(1)
                 }
                 Err(_) => {
-                    err_writer.write_all(format!("{} is not a valid command\n", arg).as_bytes())
+                    err_writer.write_all(format!("{} is not a valid command\n", arg).as_bytes())?;
+                    Ok(1)
                 }
             }
         } else {
-            err_writer.write_all(b"type: not enough arguments")
+            err_writer.write_all(b"type: not enough arguments")?;
+            Ok(2)
         }
     }
 }
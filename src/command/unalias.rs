@@ -0,0 +1,48 @@
+pub struct Unalias;
+
+impl<W: std::io::Write> super::Runnable<W> for Unalias {
+    fn run(
+        &self,
+        args: Vec<String>,
+        _out_writer: &mut W,
+        err_writer: &mut W,
+        shell: &mut crate::shell::Shell,
+    ) -> std::io::Result<i32> {
+        let mut args = &args[1..];
+
+        let mut all = false;
+        while let Some(flag) = args.first().filter(|arg| arg.starts_with('-')) {
+            match flag.as_str() {
+                "-a" => all = true,
+                "--" => {
+                    args = &args[1..];
+                    break;
+                }
+                _ => {
+                    writeln!(err_writer, "unalias: {}: invalid option", flag)?;
+                    writeln!(err_writer, "unalias: usage: unalias [-a] name [name ...]")?;
+                    return Ok(2);
+                }
+            }
+            args = &args[1..];
+        }
+
+        if all {
+            shell.clear_aliases();
+            return Ok(0);
+        }
+        if args.is_empty() {
+            writeln!(err_writer, "unalias: usage: unalias [-a] name [name ...]")?;
+            return Ok(2);
+        }
+
+        let mut status = 0;
+        for name in args {
+            if !shell.unalias(name) {
+                writeln!(err_writer, "unalias: {}: not found", name)?;
+                status = 1;
+            }
+        }
+        Ok(status)
+    }
+}
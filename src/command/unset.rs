@@ -0,0 +1,44 @@
+pub struct Unset;
+
+impl<W: std::io::Write> super::Runnable<W> for Unset {
+    fn run(
+        &self,
+        args: Vec<String>,
+        _out_writer: &mut W,
+        err_writer: &mut W,
+        shell: &mut crate::shell::Shell,
+    ) -> std::io::Result<i32> {
+        let mut args = &args[1..];
+
+        let mut functions = false;
+        while let Some(flag) = args.first().filter(|arg| arg.starts_with('-')) {
+            match flag.as_str() {
+                "-v" => functions = false,
+                "-f" => functions = true,
+                "--" => {
+                    args = &args[1..];
+                    break;
+                }
+                _ => {
+                    writeln!(err_writer, "unset: {}: invalid option", flag)?;
+                    writeln!(err_writer, "unset: usage: unset [-f] [-v] [name ...]")?;
+                    return Ok(2);
+                }
+            }
+            args = &args[1..];
+        }
+
+        let mut status = 0;
+        for name in args {
+            if functions {
+                shell.unset_function(name);
+            } else if crate::parser::expand::is_name(name) {
+                shell.unset_var(name);
+            } else {
+                writeln!(err_writer, "unset: `{}': not a valid identifier", name)?;
+                status = 1;
+            }
+        }
+        Ok(status)
+    }
+}
@@ -0,0 +1,59 @@
+use crate::job::Status;
+
+pub struct Wait;
+
+impl<W: std::io::Write> super::Runnable<W> for Wait {
+    fn run(
+        &self,
+        args: Vec<String>,
+        _out_writer: &mut W,
+        err_writer: &mut W,
+        shell: &mut crate::shell::Shell,
+    ) -> std::io::Result<i32> {
+        let jobs = shell.jobs_mut();
+
+        if args.len() == 1 {
+            // Wait for every running job; stopped ones would never finish.
+            let ids: Vec<usize> = jobs
+                .iter()
+                .filter(|job| job.status() == Status::Running)
+                .map(|job| job.id)
+                .collect();
+            for id in ids {
+                jobs.wait(id)?;
+            }
+            return Ok(0);
+        }
+
+        let mut status = 0;
+        for arg in &args[1..] {
+            status = if arg.starts_with('%') {
+                match jobs.resolve(Some(arg)) {
+                    Ok(id) => jobs.wait(id)?.last().copied().unwrap_or(0),
+                    Err(e) => {
+                        writeln!(err_writer, "wait: {}", e)?;
+                        127
+                    }
+                }
+            } else if let Ok(pid) = arg.parse::<libc::pid_t>() {
+                match jobs.find_pid(pid) {
+                    Some(id) => {
+                        let index = jobs.get(id).and_then(|job| {
+                            job.processes.iter().position(|process| process.pid == pid)
+                        });
+                        let statuses = jobs.wait(id)?;
+                        index.and_then(|i| statuses.get(i)).copied().unwrap_or(0)
+                    }
+                    None => {
+                        writeln!(err_writer, "wait: pid {} is not a child of this shell", pid)?;
+                        127
+                    }
+                }
+            } else {
+                writeln!(err_writer, "wait: `{}': not a pid or valid job spec", arg)?;
+                2
+            };
+        }
+        Ok(status)
+    }
+}
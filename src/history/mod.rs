@@ -0,0 +1,230 @@
+use std::fs::OpenOptions;
+use std::io::{self, Read, Seek, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::fs::OpenOptionsExt;
+
+use crate::parser::pattern::Pattern;
+use crate::shell::Shell;
+
+/// How many entries are kept when `HISTSIZE` is unset.
+const DEFAULT_SIZE: usize = 500;
+
+/// What the history variables ask for, read from the shell each time they
+/// are needed so that changes apply at once.
+pub struct Settings {
+    /// `HISTFILE`, unless unset or empty.
+    file: Option<String>,
+    /// `HISTSIZE`: how many entries to keep, or `None` for no limit.
+    size: Option<usize>,
+    /// `HISTFILESIZE`, defaulting to `HISTSIZE`: how many lines to keep in
+    /// the file.
+    file_size: Option<usize>,
+    /// The colon-separated words of `HISTCONTROL`.
+    control: Vec<String>,
+    /// The colon-separated patterns of `HISTIGNORE`.
+    ignore: Vec<String>,
+}
+
+impl Settings {
+    pub fn new(shell: &Shell) -> Self {
+        let list = |var| {
+            shell
+                .get_var(var)
+                .map(|value: String| value.split(':').map(String::from).collect())
+                .unwrap_or_default()
+        };
+        let entries = size(shell, "HISTSIZE");
+        Settings {
+            file: shell.get_var("HISTFILE").filter(|path| !path.is_empty()),
+            size: entries,
+            file_size: match shell.get_var("HISTFILESIZE") {
+                Some(_) => size(shell, "HISTFILESIZE"),
+                None => entries,
+            },
+            control: list("HISTCONTROL"),
+            ignore: list("HISTIGNORE"),
+        }
+    }
+
+    fn controls(&self, word: &str) -> bool {
+        self.control.iter().any(|control| control == word)
+    }
+
+    /// Whether `line` matches one of the `HISTIGNORE` patterns, where `&`
+    /// stands for the previous entry.
+    fn ignores(&self, line: &str, previous: Option<&str>) -> bool {
+        self.ignore.iter().any(|pattern| match pattern.as_str() {
+            "" => false,
+            "&" => previous == Some(line),
+            pattern => {
+                let chars: Vec<_> = pattern.chars().map(|ch| (ch, false)).collect();
+                Pattern::new(&chars).matches(line)
+            }
+        })
+    }
+}
+
+/// The lines entered in an interactive shell, kept across sessions in
+/// `HISTFILE`.
+pub struct History {
+    entries: Vec<String>,
+    /// How many of the last entries have not been written to the file yet.
+    unsaved: usize,
+    /// Set once the file has been read, which only interactive shells do, so
+    /// that other shells never write it.
+    persistent: bool,
+}
+
+impl History {
+    pub fn new() -> Self {
+        History {
+            entries: Vec::new(),
+            unsaved: 0,
+            persistent: false,
+        }
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Reads the history file named by `HISTFILE`, and from then on writes
+    /// new entries back to it.
+    pub fn load(&mut self, settings: &Settings) {
+        self.persistent = true;
+        let Some(path) = &settings.file else {
+            return;
+        };
+        let Ok(text) = std::fs::read(path) else {
+            return;
+        };
+        self.entries
+            .extend(String::from_utf8_lossy(&text).lines().map(String::from));
+        self.unsaved = 0;
+        self.truncate(settings.size);
+    }
+
+    /// Records a line unless `HISTCONTROL` or `HISTIGNORE` rule it out,
+    /// returning whether it was recorded.
+    pub fn add(&mut self, line: &str, settings: &Settings) -> bool {
+        if line.trim().is_empty() || settings.size == Some(0) {
+            return false;
+        }
+
+        let both = settings.controls("ignoreboth");
+        if (both || settings.controls("ignorespace")) && line.starts_with([' ', '\t']) {
+            return false;
+        }
+        let previous = self.entries.last().map(String::as_str);
+        if (both || settings.controls("ignoredups")) && previous == Some(line) {
+            return false;
+        }
+        if settings.ignores(line, previous) {
+            return false;
+        }
+
+        if settings.controls("erasedups") {
+            let first_unsaved = self.entries.len() - self.unsaved;
+            let mut index = 0;
+            self.entries.retain(|entry| {
+                let keep = entry != line;
+                if !keep && index >= first_unsaved {
+                    self.unsaved -= 1;
+                }
+                index += 1;
+                keep
+            });
+        }
+        self.entries.push(line.to_string());
+        self.unsaved += 1;
+        self.truncate(settings.size);
+        true
+    }
+
+    /// Appends the entries added since the last save to the history file,
+    /// then cuts the file down to `HISTFILESIZE` lines. The file is locked
+    /// meanwhile so that shells exiting together do not mix their lines.
+    pub fn save(&mut self, settings: &Settings) -> io::Result<()> {
+        if !self.persistent || self.unsaved == 0 {
+            return Ok(());
+        }
+        let Some(path) = &settings.file else {
+            return Ok(());
+        };
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .mode(0o600)
+            .open(path)?;
+        let _lock = Lock::exclusive(file.as_raw_fd())?;
+
+        let mut new = String::new();
+        for entry in &self.entries[self.entries.len() - self.unsaved..] {
+            new.push_str(entry);
+            new.push('\n');
+        }
+        file.write_all(new.as_bytes())?;
+        self.unsaved = 0;
+
+        if let Some(limit) = settings.file_size {
+            let mut text = String::new();
+            file.rewind()?;
+            file.read_to_string(&mut text)?;
+            let lines: Vec<&str> = text.lines().collect();
+            if lines.len() > limit {
+                let mut kept = lines[lines.len() - limit..].join("\n");
+                if !kept.is_empty() {
+                    kept.push('\n');
+                }
+                file.set_len(0)?;
+                file.write_all(kept.as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops the oldest entries beyond `limit`.
+    fn truncate(&mut self, limit: Option<usize>) {
+        if let Some(limit) = limit.filter(|&limit| self.entries.len() > limit) {
+            self.entries.drain(..self.entries.len() - limit);
+            self.unsaved = self.unsaved.min(limit);
+        }
+    }
+}
+
+/// A size limit from `var`: unset or not a number means the default, and a
+/// negative number means no limit.
+fn size(shell: &Shell, var: &str) -> Option<usize> {
+    match shell
+        .get_var(var)
+        .and_then(|size| size.trim().parse::<i64>().ok())
+    {
+        None => Some(DEFAULT_SIZE),
+        Some(size) => usize::try_from(size).ok(),
+    }
+}
+
+/// An advisory lock on an open file, released when dropped.
+struct Lock(RawFd);
+
+impl Lock {
+    fn exclusive(fd: RawFd) -> io::Result<Self> {
+        loop {
+            if unsafe { libc::flock(fd, libc::LOCK_EX) } == 0 {
+                return Ok(Lock(fd));
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        unsafe { libc::flock(self.0, libc::LOCK_UN) };
+    }
+}
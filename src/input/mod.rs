@@ -1,3 +1,7 @@
+mod script;
+
+pub use script::Script;
+
 use rustyline::{
     completion::{Completer, Pair},
     error::ReadlineError,
@@ -67,7 +71,7 @@ impl Completer for ShellCompleter {
         let line_parts: Vec<&str> = line[..pos].split_whitespace().collect();
 
         if line_parts.is_empty() || (line_parts.len() == 1 && !line.ends_with(' ')) {
-            let prefix = line_parts.get(0).map_or("", |s| *s);
+            let prefix = line_parts.first().map_or("", |s| *s);
 
             let mut matches: Vec<_> = self
                 .commands
@@ -109,13 +113,9 @@ impl Highlighter for ShellCompleter {
     fn highlight_prompt<'b, 's: 'b, 'p: 'b>(
         &'s self,
         prompt: &'p str,
-        default: bool,
+        _default: bool,
     ) -> Cow<'b, str> {
-        if default {
-            Borrowed(prompt)
-        } else {
-            Borrowed(prompt)
-        }
+        Borrowed(prompt)
     }
 
     fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
@@ -136,8 +136,14 @@ impl Hinter for ShellCompleter {
 }
 
 impl Validator for ShellCompleter {
-    fn validate(&self, _ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
-        Ok(ValidationResult::Valid(None))
+    /// Keeps reading lines while the input is unfinished, e.g. until a
+    /// here-document reaches its delimiter.
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        if crate::parser::Parser::is_complete(ctx.input()) {
+            Ok(ValidationResult::Valid(None))
+        } else {
+            Ok(ValidationResult::Incomplete)
+        }
     }
 }
 
@@ -0,0 +1,51 @@
+use std::io;
+
+/// Where a non-interactive shell reads its commands from, one line at a time.
+pub enum Script {
+    /// The text of a script file or of a `-c` command string.
+    Text(std::vec::IntoIter<String>),
+    /// Standard input, read a byte at a time so that the commands being run
+    /// get whatever follows the line they are on.
+    Stdin,
+}
+
+impl Script {
+    pub fn from_text(text: &str) -> Self {
+        let lines: Vec<String> = text.lines().map(str::to_string).collect();
+        Script::Text(lines.into_iter())
+    }
+}
+
+impl Iterator for Script {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Script::Text(lines) => lines.next().map(Ok),
+            Script::Stdin => read_stdin_line().transpose(),
+        }
+    }
+}
+
+/// Reads a line from stdin without buffering past its end, or `None` at end
+/// of input.
+fn read_stdin_line() -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    let mut byte = [0];
+    loop {
+        // `io::Stdin` reads ahead, so go to the descriptor itself.
+        match unsafe { libc::read(libc::STDIN_FILENO, byte.as_mut_ptr().cast(), 1) } {
+            0 if line.is_empty() => return Ok(None),
+            0 => break,
+            1 if byte[0] == b'\n' => break,
+            1 => line.push(byte[0]),
+            _ => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+        }
+    }
+    Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+}
@@ -0,0 +1,521 @@
+use std::io::{self, IsTerminal, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+use crate::command::exit_code;
+
+/// Signals meant for the foreground job: an interactive shell ignores them
+/// itself, so that Ctrl-C or Ctrl-Z never take it down, and restores them in
+/// the commands it runs.
+const FOREGROUND_SIGNALS: [libc::c_int; 5] = [
+    libc::SIGINT,
+    libc::SIGQUIT,
+    libc::SIGTSTP,
+    libc::SIGTTIN,
+    libc::SIGTTOU,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Running,
+    Stopped,
+    /// Finished, with its exit status.
+    Done(i32),
+}
+
+/// One process of a job. Stages that never started have no pid.
+#[derive(Debug)]
+pub struct Process {
+    pub pid: libc::pid_t,
+    pub status: Status,
+}
+
+impl Process {
+    pub fn started(pid: libc::pid_t) -> Self {
+        Process {
+            pid,
+            status: Status::Running,
+        }
+    }
+
+    pub fn finished(code: i32) -> Self {
+        Process {
+            pid: 0,
+            status: Status::Done(code),
+        }
+    }
+}
+
+/// A pipeline, or a whole and-or list run in the background, whose processes
+/// are controlled together.
+#[derive(Debug)]
+pub struct Job {
+    /// The number of `%n`, or 0 until the job enters the table.
+    pub id: usize,
+    /// The process group of every process in the job, or 0 without job
+    /// control.
+    pub pgid: libc::pid_t,
+    pub processes: Vec<Process>,
+    /// The command as typed, for listings.
+    pub command: String,
+    /// Whether its state changed since it was last reported.
+    changed: bool,
+}
+
+impl Job {
+    pub fn new(pgid: libc::pid_t, processes: Vec<Process>, command: String) -> Self {
+        Job {
+            id: 0,
+            pgid,
+            processes,
+            command,
+            changed: false,
+        }
+    }
+
+    /// The job is done once every process is, and stopped once none is
+    /// running but some are stopped.
+    pub fn status(&self) -> Status {
+        let statuses = self.processes.iter().map(|process| process.status);
+        if statuses.clone().any(|status| status == Status::Running) {
+            Status::Running
+        } else if statuses.clone().any(|status| status == Status::Stopped) {
+            Status::Stopped
+        } else {
+            Status::Done(self.statuses().last().copied().unwrap_or(0))
+        }
+    }
+
+    /// The exit status of every process, like `PIPESTATUS`. A stopped process
+    /// counts as killed by `SIGTSTP`.
+    pub fn statuses(&self) -> Vec<i32> {
+        self.processes
+            .iter()
+            .map(|process| match process.status {
+                Status::Done(code) => code,
+                _ => 128 + libc::SIGTSTP,
+            })
+            .collect()
+    }
+
+    /// The pid `$!` refers to for this job.
+    pub fn last_pid(&self) -> libc::pid_t {
+        self.processes
+            .iter()
+            .rev()
+            .map(|process| process.pid)
+            .find(|pid| *pid != 0)
+            .unwrap_or(0)
+    }
+
+    /// Blocks until every process has finished or one of them stops.
+    fn wait(&mut self) -> io::Result<()> {
+        for process in &mut self.processes {
+            if process.status != Status::Running {
+                continue;
+            }
+            match wait_pid(process.pid, libc::WUNTRACED)? {
+                Some(status @ Status::Stopped) => {
+                    process.status = status;
+                    // The rest of the job stops along with it.
+                    break;
+                }
+                Some(status) => process.status = status,
+                None => {}
+            }
+        }
+        for process in &mut self.processes {
+            if self.pgid != 0 && process.status == Status::Running {
+                process.status = Status::Stopped;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends `SIGCONT` to every process of the job.
+    fn resume(&mut self) {
+        for process in &mut self.processes {
+            if process.status == Status::Stopped {
+                process.status = Status::Running;
+            }
+        }
+        if self.pgid != 0 {
+            unsafe { libc::kill(-self.pgid, libc::SIGCONT) };
+        } else {
+            for process in &self.processes {
+                if process.pid != 0 {
+                    unsafe { libc::kill(process.pid, libc::SIGCONT) };
+                }
+            }
+        }
+    }
+
+    /// Polls the processes without blocking. Returns whether anything changed.
+    fn poll(&mut self) -> io::Result<bool> {
+        let mut changed = false;
+        for process in &mut self.processes {
+            if matches!(process.status, Status::Done(_)) {
+                continue;
+            }
+            let flags = libc::WNOHANG | libc::WUNTRACED | libc::WCONTINUED;
+            if let Some(status) = wait_pid(process.pid, flags)? {
+                changed |= status != process.status;
+                process.status = status;
+            }
+        }
+        Ok(changed)
+    }
+}
+
+/// Where a new process of a job goes: into the process group `pgid`, or a
+/// new one it leads if that is 0, and whether that group gets the terminal.
+#[derive(Debug, Clone, Copy)]
+pub struct Group {
+    pub pgid: libc::pid_t,
+    pub foreground: bool,
+}
+
+/// The table of jobs started from this shell, and the terminal their process
+/// groups take turns on.
+pub struct Jobs {
+    jobs: Vec<Job>,
+    /// Job ids from least to most recently started or stopped. The last one
+    /// is the current job `%+` and the one before it the previous job `%-`.
+    recent: Vec<usize>,
+    /// The controlling terminal when job control is on.
+    terminal: Option<OwnedFd>,
+    shell_pgid: libc::pid_t,
+}
+
+impl Jobs {
+    /// An empty table, with job control off.
+    pub fn new() -> Self {
+        Jobs {
+            jobs: Vec::new(),
+            recent: Vec::new(),
+            terminal: None,
+            shell_pgid: unsafe { libc::getpgrp() },
+        }
+    }
+
+    /// Turns job control on if the shell is reading from a terminal: the
+    /// shell gets a process group of its own, takes the terminal and ignores
+    /// the signals meant for the foreground job. Only interactive shells do
+    /// this.
+    pub fn enable(&mut self) {
+        if !io::stdin().is_terminal() {
+            return;
+        }
+
+        let fd = unsafe { libc::fcntl(libc::STDIN_FILENO, libc::F_DUPFD_CLOEXEC, 10) };
+        if fd == -1 {
+            return;
+        }
+        for signal in FOREGROUND_SIGNALS {
+            unsafe { libc::signal(signal, libc::SIG_IGN) };
+        }
+        unsafe {
+            let pid = libc::getpid();
+            libc::setpgid(pid, pid);
+            libc::tcsetpgrp(fd, pid);
+        }
+        self.shell_pgid = unsafe { libc::getpgrp() };
+        self.terminal = Some(unsafe { OwnedFd::from_raw_fd(fd) });
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.terminal.is_some()
+    }
+
+    /// Turns job control off, as in a subshell, which must leave the
+    /// terminal alone.
+    pub fn disable(&mut self) {
+        self.terminal = None;
+    }
+
+    /// The process group for the next process of a job whose group is
+    /// `pgid`, or `None` without job control.
+    pub fn group(&self, pgid: libc::pid_t, foreground: bool) -> Option<Group> {
+        self.terminal.as_ref().map(|_| Group { pgid, foreground })
+    }
+
+    /// Records the process group of a process that was just started, from the
+    /// shell's side too so that it is set whichever side runs first, and hands
+    /// the terminal to a foreground job. Returns the job's process group.
+    pub fn join(&self, pid: libc::pid_t, group: Option<Group>) -> libc::pid_t {
+        let Some(Group { pgid, foreground }) = group else {
+            return 0;
+        };
+        let pgid = if pgid == 0 { pid } else { pgid };
+        unsafe { libc::setpgid(pid, pgid) };
+        if foreground {
+            self.give_terminal(pgid);
+        }
+        pgid
+    }
+
+    fn give_terminal(&self, pgid: libc::pid_t) {
+        if let Some(terminal) = &self.terminal {
+            unsafe { libc::tcsetpgrp(terminal.as_raw_fd(), pgid) };
+        }
+    }
+
+    /// Adds a job to the table, numbering it if it is new, and makes it the
+    /// current job.
+    pub fn add(&mut self, mut job: Job) -> usize {
+        if job.id == 0 {
+            job.id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        }
+        let id = job.id;
+        let index = self.jobs.partition_point(|other| other.id < id);
+        self.jobs.insert(index, job);
+        self.recent.retain(|other| *other != id);
+        self.recent.push(id);
+        id
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Job> {
+        let index = self.jobs.iter().position(|job| job.id == id)?;
+        self.recent.retain(|other| *other != id);
+        Some(self.jobs.remove(index))
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.id == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.iter()
+    }
+
+    /// The job containing the process `pid`.
+    pub fn find_pid(&self, pid: libc::pid_t) -> Option<usize> {
+        self.jobs
+            .iter()
+            .find(|job| job.processes.iter().any(|process| process.pid == pid))
+            .map(|job| job.id)
+    }
+
+    /// `+` for the current job, `-` for the previous one and a space for the
+    /// others, as shown by `jobs`.
+    pub fn mark(&self, id: usize) -> char {
+        let mut recent = self.recent.iter().rev();
+        if recent.next() == Some(&id) {
+            '+'
+        } else if recent.next() == Some(&id) {
+            '-'
+        } else {
+            ' '
+        }
+    }
+
+    /// Resolves a job spec: `%n`, `%+` or `%%` for the current job, `%-` for
+    /// the previous one, or `%name` for the job whose command starts with
+    /// `name`. No spec at all means the current job.
+    pub fn resolve(&self, spec: Option<&str>) -> Result<usize, String> {
+        let no_such_job = || format!("{}: no such job", spec.unwrap_or("current"));
+        let spec = spec.map(|spec| spec.strip_prefix('%').unwrap_or(spec));
+        let id = match spec {
+            None | Some("" | "+" | "%") => self.recent.last().copied(),
+            Some("-") => self.recent.iter().rev().nth(1).copied(),
+            Some(spec) => match spec.parse::<usize>() {
+                Ok(id) => self.get(id).map(|job| job.id),
+                Err(_) => {
+                    let mut matches = self.jobs.iter().filter(|job| job.command.starts_with(spec));
+                    match (matches.next(), matches.next()) {
+                        (Some(job), None) => Some(job.id),
+                        (Some(_), Some(_)) => {
+                            return Err(format!("{}: ambiguous job spec", spec));
+                        }
+                        _ => None,
+                    }
+                }
+            },
+        };
+        id.ok_or_else(no_such_job)
+    }
+
+    /// Waits for a job running in the foreground, then takes the terminal
+    /// back. A job that stops is added to the table. Returns the exit status of
+    /// each of its processes.
+    pub fn wait_foreground(&mut self, mut job: Job) -> io::Result<Vec<i32>> {
+        let waited = job.wait();
+        self.give_terminal(self.shell_pgid);
+        waited?;
+
+        let statuses = job.statuses();
+        if job.status() == Status::Stopped {
+            let id = self.add(job);
+            eprintln!();
+            self.print(id, false, &mut io::stderr())?;
+        } else if statuses.contains(&(128 + libc::SIGINT)) {
+            // Start the prompt on a fresh line after the echoed `^C`.
+            eprintln!();
+        }
+        Ok(statuses)
+    }
+
+    /// Continues a stopped or background job in the foreground, for `fg`.
+    pub fn foreground(&mut self, id: usize) -> io::Result<Vec<i32>> {
+        let Some(mut job) = self.remove(id) else {
+            return Ok(Vec::new());
+        };
+        self.give_terminal(job.pgid);
+        job.resume();
+        self.wait_foreground(job)
+    }
+
+    /// Continues a stopped job in the background, for `bg`.
+    pub fn background(&mut self, id: usize) {
+        if let Some(job) = self.jobs.iter_mut().find(|job| job.id == id) {
+            job.resume();
+        }
+    }
+
+    /// Blocks until the job finishes, removes it and returns the exit status
+    /// of its processes, for `wait`. Stopped jobs are not waited for.
+    pub fn wait(&mut self, id: usize) -> io::Result<Vec<i32>> {
+        let Some(job) = self.jobs.iter_mut().find(|job| job.id == id) else {
+            return Ok(Vec::new());
+        };
+        job.wait()?;
+        let statuses = job.statuses();
+        if matches!(job.status(), Status::Done(_)) {
+            self.remove(id);
+        }
+        Ok(statuses)
+    }
+
+    /// Checks on every job without blocking.
+    pub fn poll(&mut self) -> io::Result<()> {
+        for job in &mut self.jobs {
+            job.poll()?;
+        }
+        Ok(())
+    }
+
+    /// Drops the jobs that have finished from the table.
+    pub fn remove_done(&mut self) {
+        let done: Vec<usize> = self
+            .jobs
+            .iter()
+            .filter(|job| matches!(job.status(), Status::Done(_)))
+            .map(|job| job.id)
+            .collect();
+        for id in done {
+            self.remove(id);
+        }
+    }
+
+    /// Reports jobs that finished or stopped since the last prompt, dropping
+    /// the finished ones from the table.
+    pub fn notify(&mut self) -> io::Result<()> {
+        for job in &mut self.jobs {
+            if job.poll()? && self.terminal.is_some() {
+                // Printed below once every job is up to date.
+                job.changed = true;
+            }
+        }
+        for id in self.jobs.iter().map(|job| job.id).collect::<Vec<_>>() {
+            if self.get(id).is_some_and(|job| job.changed) {
+                self.print(id, false, &mut io::stderr())?;
+            }
+        }
+        for job in &mut self.jobs {
+            job.changed = false;
+        }
+        self.remove_done();
+        Ok(())
+    }
+
+    /// Prints a job the way `jobs` lists it, with its pid when `pid` is set.
+    pub fn print(&self, id: usize, pid: bool, out: &mut impl Write) -> io::Result<()> {
+        let Some(job) = self.get(id) else {
+            return Ok(());
+        };
+        let state = match job.status() {
+            Status::Running => "Running".to_string(),
+            Status::Stopped => "Stopped".to_string(),
+            Status::Done(0) => "Done".to_string(),
+            Status::Done(code) if code > 128 => signal_name(code - 128),
+            Status::Done(code) => format!("Exit {}", code),
+        };
+        let suffix = if job.status() == Status::Running {
+            " &"
+        } else {
+            ""
+        };
+        let pid = if pid {
+            format!("{} ", job.last_pid())
+        } else {
+            String::new()
+        };
+        writeln!(
+            out,
+            "[{}]{}  {}{:<24}{}{}",
+            job.id,
+            self.mark(id),
+            pid,
+            state,
+            job.command,
+            suffix
+        )
+    }
+}
+
+/// Sets up a freshly forked child for the job it belongs to: joins its
+/// process group, takes the terminal if the job is in the foreground, and
+/// restores the signals the shell ignores. Runs between fork and exec, so it
+/// only makes system calls.
+pub fn prepare_child(group: Option<Group>) -> io::Result<()> {
+    if let Some(Group { pgid, foreground }) = group {
+        unsafe {
+            libc::setpgid(0, pgid);
+            if foreground {
+                libc::tcsetpgrp(libc::STDIN_FILENO, libc::getpgrp());
+            }
+        }
+    }
+    for signal in FOREGROUND_SIGNALS {
+        unsafe { libc::signal(signal, libc::SIG_DFL) };
+    }
+    Ok(())
+}
+
+/// Waits for a change in the state of `pid`, returning `None` if there is
+/// none yet with `WNOHANG`.
+fn wait_pid(pid: libc::pid_t, flags: libc::c_int) -> io::Result<Option<Status>> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let mut status = 0;
+    loop {
+        match unsafe { libc::waitpid(pid, &mut status, flags) } {
+            0 => return Ok(None),
+            -1 => {
+                let err = io::Error::last_os_error();
+                match err.raw_os_error() {
+                    Some(libc::EINTR) => continue,
+                    // Already reaped elsewhere; nothing more will happen to it.
+                    Some(libc::ECHILD) => return Ok(Some(Status::Done(0))),
+                    _ => return Err(err),
+                }
+            }
+            _ if libc::WIFSTOPPED(status) => return Ok(Some(Status::Stopped)),
+            _ if libc::WIFCONTINUED(status) => return Ok(Some(Status::Running)),
+            _ => {
+                let code = exit_code(std::process::ExitStatus::from_raw(status));
+                return Ok(Some(Status::Done(code)));
+            }
+        }
+    }
+}
+
+/// Describes death by a signal the way job listings do, e.g. `Terminated`.
+fn signal_name(signal: i32) -> String {
+    let name = unsafe { libc::strsignal(signal) };
+    if name.is_null() {
+        return format!("Signal {}", signal);
+    }
+    unsafe { std::ffi::CStr::from_ptr(name) }
+        .to_string_lossy()
+        .into_owned()
+}
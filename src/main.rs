@@ -1,101 +1,182 @@
 mod command;
+mod history;
 mod input;
+mod job;
 mod parser;
+mod pipeline;
+mod shell;
 
-use std::io::{self, Write};
+use std::io::IsTerminal;
 
-use command::Runnable;
 use input::*;
 use parser::*;
 
+/// What the shell was asked to run on its command line.
+struct Options {
+    /// The string given with `-c`.
+    command: Option<String>,
+    /// `-i`: interactive even when not reading from a terminal.
+    interactive: bool,
+    /// `-l`, `--login`, or a name starting with `-`: read the login profiles.
+    login: bool,
+    /// `--norc`: skip the rc file of interactive shells.
+    norc: bool,
+    /// `--noprofile`: skip the login profiles.
+    noprofile: bool,
+    /// The script or, with `-c`, `$0`, followed by the positional parameters.
+    operands: Vec<String>,
+}
+
+impl Options {
+    /// Parses `[--login] [--norc] [--noprofile] [-il] [-c command [name
+    /// [arg ...]] | script [arg ...]]`.
+    fn parse(mut args: impl Iterator<Item = String>, login: bool) -> Result<Self, String> {
+        let mut options = Options {
+            command: None,
+            interactive: false,
+            login,
+            norc: false,
+            noprofile: false,
+            operands: Vec::new(),
+        };
+        let mut wants_command = false;
+        for arg in args.by_ref() {
+            match arg.as_str() {
+                "--" => break,
+                "-c" => wants_command = true,
+                "-i" => options.interactive = true,
+                "-l" | "--login" => options.login = true,
+                "--norc" => options.norc = true,
+                "--noprofile" => options.noprofile = true,
+                _ if arg.starts_with('-') && arg.len() > 1 => {
+                    return Err(format!("{}: invalid option", arg));
+                }
+                _ => {
+                    options.operands.push(arg);
+                    break;
+                }
+            }
+        }
+        options.operands.extend(args);
+
+        if wants_command {
+            if options.operands.is_empty() {
+                return Err("-c: option requires an argument".into());
+            }
+            options.command = Some(options.operands.remove(0));
+        }
+        Ok(options)
+    }
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut history = Vec::new();
+    let mut args = std::env::args();
+    let program = args.next().unwrap_or_default();
+    let options = match Options::parse(args, program.starts_with('-')) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}: {}", program, e);
+            std::process::exit(2);
+        }
+    };
+
+    let mut shell = shell::Shell::new();
+    let mut operands = options.operands.into_iter();
+    let interactive = options.command.is_none()
+        && operands.len() == 0
+        && (options.interactive || std::io::stdin().is_terminal());
+
+    if interactive {
+        shell.set_interactive();
+        shell.jobs_mut().enable();
+    }
+    if options.login && !options.noprofile {
+        source_startup_file("/etc/profile".into(), &mut shell)?;
+        if let Some(home) = shell.get_var("HOME") {
+            let profile = std::path::Path::new(&home).join(".profile");
+            source_startup_file(profile, &mut shell)?;
+        }
+    }
+    if interactive && shell.get_var("HISTFILE").is_none() {
+        if let Some(home) = shell.get_var("HOME") {
+            let file = std::path::Path::new(&home).join(".shell_history");
+            shell.set_var("HISTFILE", file.display().to_string());
+        }
+    }
+    if interactive && !options.norc {
+        if let Some(rc) = rc_file(&shell) {
+            source_startup_file(rc, &mut shell)?;
+        }
+    }
+
+    if let Some(command) = options.command {
+        if let Some(name) = operands.next() {
+            shell.set_name(name);
+        }
+        shell.set_positional(operands.collect());
+        let name = shell.name().to_string();
+        if !shell.run_script(Script::from_text(&command), &name)? {
+            std::process::exit(2);
+        }
+    } else if let Some(path) = operands.next() {
+        let text = match std::fs::read(&path) {
+            Ok(text) => String::from_utf8_lossy(&text).into_owned(),
+            Err(e) => {
+                eprintln!("{}: {}: {}", program, path, command::error_text(&e));
+                let not_found = e.kind() == std::io::ErrorKind::NotFound;
+                std::process::exit(if not_found { 127 } else { 126 });
+            }
+        };
+        shell.set_name(path.clone());
+        shell.set_positional(operands.collect());
+        if !shell.run_script(Script::from_text(&text), &path)? {
+            std::process::exit(2);
+        }
+    } else if interactive {
+        run_interactive(&mut shell)?;
+    } else if !shell.run_script(Script::Stdin, &program)? {
+        std::process::exit(2);
+    }
+
+    std::process::exit(shell.last_status());
+}
+
+/// Reads commands with the line editor until end of input.
+fn run_interactive(shell: &mut shell::Shell) -> Result<(), Box<dyn std::error::Error>> {
     let mut rl = ReadLine::new();
+    shell.load_history();
+    for entry in shell.history().entries() {
+        rl.add_history(entry);
+    }
     loop {
+        shell.jobs_mut().notify()?;
         let readline = rl.readline("$ ");
 
         match readline {
             Ok(input) => {
-                rl.add_history(&input); // add own readline implmentation in future to remove deps
-                                        // on rustyline
-
-                let ParseOutput {
-                    commands,
-                    out_target,
-                    err_target,
-                } = Parser::parse(&input)
-                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
-
-                let mut err_writer = if let Some((target, append)) = err_target {
-                    let file = std::fs::OpenOptions::new()
-                        .write(true)
-                        .create(true)
-                        .append(append)
-                        .open(&target)?;
-                    Box::new(file) as Box<dyn Write>
-                } else {
-                    Box::new(io::stderr())
-                };
-
-                let mut input: Box<dyn std::io::Read> = Box::new(io::empty());
-
-                for (i, args) in commands.iter().enumerate() {
-                    let (mut out_writer, next_input): (
-                        Box<dyn Write>,
-                        Option<Box<dyn std::io::Read>>,
-                    ) = if i == commands.len() - 1 {
-                        let writer: Box<dyn Write> = if let Some((ref target, append)) = out_target
-                        {
-                            let file = std::fs::OpenOptions::new()
-                                .write(true)
-                                .create(true)
-                                .append(append)
-                                .open(&target)?;
-                            Box::new(file)
-                        } else {
-                            Box::new(io::stdout())
-                        };
-                        (writer, None)
-                    } else {
-                        let (reader, writer) = os_pipe::pipe()?;
-                        (Box::new(writer), Some(Box::new(reader)))
-                    };
-
-                    if let Some(cmd) = args.first() {
-                        if let Ok(cmd) = cmd.parse::<command::Command>() {
-                            if i == 0 {
-                                cmd.run(
-                                    args.clone(),
-                                    None,
-                                    &mut out_writer,
-                                    &mut err_writer,
-                                    &mut history,
-                                )?;
-                            } else {
-                                cmd.run(
-                                    args.clone(),
-                                    Some(&mut input),
-                                    &mut out_writer,
-                                    &mut err_writer,
-                                    &mut history,
-                                )?;
-                            }
-
-                            if let Some(next_input) = next_input {
-                                input = next_input;
-                            }
-                        } else {
-                            writeln!(err_writer, "Error: Invalid command")?;
-                            break;
-                        }
+                if shell.add_history(&input) {
+                    rl.add_history(&input);
+                }
+
+                match Parser::parse(&input, shell.aliases()) {
+                    Ok(list) => {
+                        shell.run_list(&list)?;
+                        shell.clear_stray_flow();
+                        shell.clear_interrupt();
+                    }
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        shell.set_status(vec![2]);
                     }
                 }
             }
             Err(rustyline::error::ReadlineError::Interrupted) => {
-                std::process::exit(0);
+                // Ctrl-C only throws away the line being typed.
+                shell.set_status(vec![130]);
             }
             Err(rustyline::error::ReadlineError::Eof) => {
                 println!("CTRL-D");
+                shell.save_history();
                 break;
             }
             Err(err) => {
@@ -107,3 +188,34 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+/// The rc file read by interactive shells: `$SHELLRC` if set, otherwise
+/// `~/.shellrc`.
+fn rc_file(shell: &shell::Shell) -> Option<std::path::PathBuf> {
+    match shell.get_var("SHELLRC") {
+        Some(rc) if !rc.is_empty() => Some(rc.into()),
+        _ => shell
+            .get_var("HOME")
+            .map(|home| std::path::Path::new(&home).join(".shellrc")),
+    }
+}
+
+/// Runs a startup file if it exists. Errors in it are reported with the file
+/// and line, but the shell starts regardless.
+fn source_startup_file(
+    path: std::path::PathBuf,
+    shell: &mut shell::Shell,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let text = match std::fs::read(&path) {
+        Ok(text) => String::from_utf8_lossy(&text).into_owned(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), command::error_text(&e));
+            return Ok(());
+        }
+    };
+    let source = path.display().to_string();
+    shell.run_script(Script::from_text(&text), &source)?;
+    shell.clear_interrupt();
+    Ok(())
+}
@@ -0,0 +1,114 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::rc::Rc;
+
+use super::{Parser, Token};
+
+/// Whether `name` can be defined as an alias: a non-empty word without
+/// quoting, expansion or operator characters.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name
+            .chars()
+            .any(|c| c.is_whitespace() || "/$`\\='\"|&;<>()".contains(c))
+}
+
+/// A token waiting to be looked at, with the aliases whose expansion produced
+/// it: those are not expanded again, so an alias can refer to a command of the
+/// same name and aliases referring to each other do not loop.
+struct Pending {
+    token: Token,
+    expanding: Rc<Vec<String>>,
+    /// Set for the word after an alias whose value ends in a blank, which is
+    /// checked for an alias even though it is not a command name.
+    after_blank: bool,
+}
+
+/// Replaces each alias found where a command name can start with the tokens
+/// of its value, before the tokens are parsed into commands.
+pub(super) fn expand_aliases(
+    tokens: Vec<Token>,
+    aliases: &BTreeMap<String, String>,
+) -> Result<Vec<Token>, String> {
+    if aliases.is_empty() {
+        return Ok(tokens);
+    }
+
+    let mut queue: VecDeque<_> = tokens
+        .into_iter()
+        .map(|token| Pending {
+            token,
+            expanding: Rc::default(),
+            after_blank: false,
+        })
+        .collect();
+    let mut expanded = Vec::new();
+    let mut command_position = true;
+    let mut redirect_target = false;
+
+    while let Some(pending) = queue.pop_front() {
+        if let Token::Word(word) = &pending.token {
+            let candidate = (command_position && !redirect_target) || pending.after_blank;
+            let value = aliases
+                .get(word)
+                .filter(|_| candidate && !pending.expanding.contains(word));
+            if let Some(value) = value {
+                let mut expanding = (*pending.expanding).clone();
+                expanding.push(word.clone());
+                let expanding = Rc::new(expanding);
+
+                let mut parser = Parser::new(value);
+                parser.tokenize()?;
+                let replacement: Vec<_> = parser
+                    .tokens
+                    .into_iter()
+                    .map(|token| Pending {
+                        token,
+                        expanding: expanding.clone(),
+                        after_blank: false,
+                    })
+                    .collect();
+                if value.ends_with([' ', '\t']) {
+                    if let Some(next) = queue.front_mut() {
+                        next.after_blank = true;
+                    }
+                }
+                for pending in replacement.into_iter().rev() {
+                    queue.push_front(pending);
+                }
+                continue;
+            }
+        }
+
+        match &pending.token {
+            Token::Word(_) if redirect_target => redirect_target = false,
+            Token::Word(word) => {
+                command_position = command_position && keeps_command_position(word);
+            }
+            Token::Redirect(..) | Token::RedirectAll(_) => redirect_target = true,
+            Token::HereDoc { .. } => {}
+            Token::DoubleSemi | Token::Arithmetic(_) => command_position = false,
+            Token::Pipe
+            | Token::And
+            | Token::Or
+            | Token::Semi
+            | Token::Newline
+            | Token::Amp
+            | Token::LParen
+            | Token::RParen => command_position = true,
+        }
+        expanded.push(pending.token);
+    }
+    Ok(expanded)
+}
+
+/// Whether a word read where a command name can start leaves the next word
+/// there too: reserved words that open a list, and assignments.
+fn keeps_command_position(word: &str) -> bool {
+    const OPENING: [&str; 9] = [
+        "{", "if", "then", "elif", "else", "while", "until", "do", "!",
+    ];
+    OPENING.contains(&word)
+        || word
+            .split_once('=')
+            .is_some_and(|(name, _)| super::expand::is_name(name))
+}
@@ -0,0 +1,435 @@
+use crate::shell::Shell;
+
+/// Evaluates an arithmetic expression as used by `for ((...))`, after
+/// expanding any parameters and command substitutions in it. Variables can
+/// be named without `$` and assigned with `=`, `+=`, `++` and the like.
+pub fn evaluate(expression: &str, shell: &mut Shell) -> Result<i64, String> {
+    evaluate_nested(expression, shell, 0)
+}
+
+/// How deeply variables holding expressions may refer to one another, so
+/// that `a=a` is an error instead of endless recursion.
+const MAX_DEPTH: usize = 128;
+
+/// Evaluates an expression found in a variable, `depth` variables deep.
+fn evaluate_nested(expression: &str, shell: &mut Shell, depth: usize) -> Result<i64, String> {
+    let expanded = super::expand::expand_assignment(expression, shell)?;
+    let tokens = tokenize(&expanded)?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+
+    let mut evaluator = Evaluator {
+        tokens: &tokens,
+        pos: 0,
+        shell,
+        depth,
+    };
+    let value = evaluator.comma(true)?;
+    match evaluator.tokens.get(evaluator.pos) {
+        None => Ok(value),
+        Some(token) => Err(format!(
+            "{}: syntax error in expression (error token is \"{}\")",
+            expanded.trim(),
+            token
+        )),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    /// Any operator, including parentheses.
+    Op(&'static str),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Name(name) => write!(f, "{}", name),
+            Token::Op(op) => write!(f, "{}", op),
+        }
+    }
+}
+
+/// Operators, longest first so that `<<=` is not read as `<<` and `=`.
+const OPERATORS: [&str; 39] = [
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=",
+    "*=", "/=", "%=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "=", "!", "~", "&", "^",
+    "|", "?", ":", ",", "(", ")",
+];
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while let Some(ch) = rest.chars().next() {
+        if ch.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '#')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Number(number(&rest[..end])?));
+            rest = &rest[end..];
+        } else if ch.is_ascii_alphabetic() || ch == '_' {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else {
+            let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) else {
+                return Err(format!(
+                    "{}: syntax error: invalid arithmetic operator (error token is \"{}\")",
+                    text.trim(),
+                    rest
+                ));
+            };
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Parses an integer constant: decimal, octal with a leading `0`, hex with
+/// `0x`, or `base#digits`.
+fn number(text: &str) -> Result<i64, String> {
+    let invalid = || {
+        format!(
+            "{}: value too great for base (error token is \"{}\")",
+            text, text
+        )
+    };
+    let (base, digits) = if let Some((base, digits)) = text.split_once('#') {
+        let base: u32 = base.parse().map_err(|_| invalid())?;
+        if !(2..=36).contains(&base) {
+            return Err(format!("{}: invalid arithmetic base", text));
+        }
+        (base, digits)
+    } else if let Some(hex) = text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+        (16, hex)
+    } else if text.len() > 1 && text.starts_with('0') {
+        (8, &text[1..])
+    } else {
+        (10, text)
+    };
+    i64::from_str_radix(digits, base).map_err(|_| invalid())
+}
+
+/// A recursive descent evaluator. With `live` unset, operands are parsed but
+/// have no side effects, for the branches that short-circuiting skips.
+struct Evaluator<'a, 'b> {
+    tokens: &'a [Token],
+    pos: usize,
+    shell: &'b mut Shell,
+    /// How many variables deep the expression is.
+    depth: usize,
+}
+
+impl Evaluator<'_, '_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn eat(&mut self, op: &'static str) -> bool {
+        self.eat_any(&[op]).is_some()
+    }
+
+    /// The next token if it is one of `ops`.
+    fn eat_any(&mut self, ops: &[&'static str]) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Op(op)) if ops.contains(op) => {
+                let op = *op;
+                self.pos += 1;
+                Some(op)
+            }
+            _ => None,
+        }
+    }
+
+    fn error(&self) -> String {
+        match self.peek() {
+            Some(token) => format!(
+                "syntax error: operand expected (error token is \"{}\")",
+                token
+            ),
+            None => "syntax error: operand expected".into(),
+        }
+    }
+
+    fn comma(&mut self, live: bool) -> Result<i64, String> {
+        let mut value = self.assignment(live)?;
+        while self.eat(",") {
+            value = self.assignment(live)?;
+        }
+        Ok(value)
+    }
+
+    fn assignment(&mut self, live: bool) -> Result<i64, String> {
+        if let (Some(Token::Name(name)), Some(Token::Op(op))) =
+            (self.tokens.get(self.pos), self.tokens.get(self.pos + 1))
+        {
+            let op = *op;
+            if op.ends_with('=') && !matches!(op, "==" | "!=" | "<=" | ">=") {
+                let name = name.clone();
+                self.pos += 2;
+                let rhs = self.assignment(live)?;
+                if !live {
+                    return Ok(rhs);
+                }
+                let value = match &op[..op.len() - 1] {
+                    "" => rhs,
+                    binary => apply(binary, self.variable(&name)?, rhs)?,
+                };
+                self.shell.set_var(&name, value.to_string());
+                return Ok(value);
+            }
+        }
+        self.conditional(live)
+    }
+
+    fn conditional(&mut self, live: bool) -> Result<i64, String> {
+        let condition = self.binary(0, live)?;
+        if !self.eat("?") {
+            return Ok(condition);
+        }
+        let then = self.assignment(live && condition != 0)?;
+        if !self.eat(":") {
+            return Err("syntax error: `:' expected for conditional expression".into());
+        }
+        let otherwise = self.assignment(live && condition == 0)?;
+        Ok(if condition != 0 { then } else { otherwise })
+    }
+
+    /// Binary operators by precedence, loosest first.
+    const LEVELS: [&'static [&'static str]; 10] = [
+        &["||"],
+        &["&&"],
+        &["|"],
+        &["^"],
+        &["&"],
+        &["==", "!="],
+        &["<", ">", "<=", ">="],
+        &["<<", ">>"],
+        &["+", "-"],
+        &["*", "/", "%"],
+    ];
+
+    fn binary(&mut self, level: usize, live: bool) -> Result<i64, String> {
+        if level == Self::LEVELS.len() {
+            return self.power(live);
+        }
+        let mut value = self.binary(level + 1, live)?;
+        while let Some(op) = self.eat_any(Self::LEVELS[level]) {
+            let rhs = match op {
+                "&&" => self.binary(level + 1, live && value != 0)?,
+                "||" => self.binary(level + 1, live && value == 0)?,
+                _ => self.binary(level + 1, live)?,
+            };
+            value = if live { apply(op, value, rhs)? } else { 0 };
+        }
+        Ok(value)
+    }
+
+    fn power(&mut self, live: bool) -> Result<i64, String> {
+        let base = self.unary(live)?;
+        if !self.eat("**") {
+            return Ok(base);
+        }
+        let exponent = self.power(live)?;
+        if live {
+            apply("**", base, exponent)
+        } else {
+            Ok(0)
+        }
+    }
+
+    fn unary(&mut self, live: bool) -> Result<i64, String> {
+        if let Some(op) = self.eat_any(&["++", "--"]) {
+            let Some(Token::Name(name)) = self.peek().cloned() else {
+                return Err(self.error());
+            };
+            self.pos += 1;
+            let value = self
+                .variable(&name)?
+                .wrapping_add(if op == "++" { 1 } else { -1 });
+            if live {
+                self.shell.set_var(&name, value.to_string());
+            }
+            return Ok(value);
+        }
+        match self.eat_any(&["-", "+", "!", "~"]) {
+            Some("-") => Ok(self.unary(live)?.wrapping_neg()),
+            Some("+") => self.unary(live),
+            Some("!") => Ok((self.unary(live)? == 0) as i64),
+            Some(_) => Ok(!self.unary(live)?),
+            None => self.postfix(live),
+        }
+    }
+
+    fn postfix(&mut self, live: bool) -> Result<i64, String> {
+        match self.peek().cloned() {
+            Some(Token::Number(n)) => {
+                self.pos += 1;
+                Ok(n)
+            }
+            Some(Token::Name(name)) => {
+                self.pos += 1;
+                let value = self.variable(&name)?;
+                if let Some(op) = self.eat_any(&["++", "--"]) {
+                    let updated = value.wrapping_add(if op == "++" { 1 } else { -1 });
+                    if live {
+                        self.shell.set_var(&name, updated.to_string());
+                    }
+                }
+                Ok(value)
+            }
+            Some(Token::Op("(")) => {
+                self.pos += 1;
+                let value = self.comma(live)?;
+                if !self.eat(")") {
+                    return Err("missing `)'".into());
+                }
+                Ok(value)
+            }
+            _ => Err(self.error()),
+        }
+    }
+
+    /// The value of a variable, which may itself hold an expression. Unset
+    /// and empty variables count as 0.
+    fn variable(&mut self, name: &str) -> Result<i64, String> {
+        match self.shell.get_var(name) {
+            Some(value) if !value.trim().is_empty() => match value.trim().parse() {
+                Ok(n) => Ok(n),
+                Err(_) if self.depth >= MAX_DEPTH => Err(format!(
+                    "{}: expression recursion level exceeded (error token is \"{}\")",
+                    name, name
+                )),
+                Err(_) => evaluate_nested(&value, self.shell, self.depth + 1),
+            },
+            _ => Ok(0),
+        }
+    }
+}
+
+fn apply(op: &str, lhs: i64, rhs: i64) -> Result<i64, String> {
+    Ok(match op {
+        "+" => lhs.wrapping_add(rhs),
+        "-" => lhs.wrapping_sub(rhs),
+        "*" => lhs.wrapping_mul(rhs),
+        "/" | "%" if rhs == 0 => return Err("division by 0".into()),
+        "/" => lhs.wrapping_div(rhs),
+        "%" => lhs.wrapping_rem(rhs),
+        "**" if rhs < 0 => return Err("exponent less than 0".into()),
+        "**" => lhs.wrapping_pow(rhs.min(u32::MAX as i64) as u32),
+        "<<" => lhs.wrapping_shl(rhs as u32),
+        ">>" => lhs.wrapping_shr(rhs as u32),
+        "&" => lhs & rhs,
+        "^" => lhs ^ rhs,
+        "|" => lhs | rhs,
+        "<" => (lhs < rhs) as i64,
+        ">" => (lhs > rhs) as i64,
+        "<=" => (lhs <= rhs) as i64,
+        ">=" => (lhs >= rhs) as i64,
+        "==" => (lhs == rhs) as i64,
+        "!=" => (lhs != rhs) as i64,
+        "&&" => (lhs != 0 && rhs != 0) as i64,
+        "||" => (lhs != 0 || rhs != 0) as i64,
+        _ => unreachable!("unknown operator {}", op),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expression: &str) -> Result<i64, String> {
+        evaluate(expression, &mut Shell::new())
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(7));
+        assert_eq!(eval("(1 + 2) * 3"), Ok(9));
+        assert_eq!(eval("10 - 4 - 3"), Ok(3));
+        assert_eq!(eval("2 ** 3 ** 2"), Ok(512));
+        assert_eq!(eval("-2 ** 2"), Ok(4));
+        assert_eq!(eval("1 < 2 == 1"), Ok(1));
+        assert_eq!(eval("7 % 3 | 4 & 6"), Ok(5));
+        assert_eq!(eval("!0 + ~0"), Ok(0));
+    }
+
+    #[test]
+    fn number_bases() {
+        assert_eq!(eval("010"), Ok(8));
+        assert_eq!(eval("0x1f"), Ok(31));
+        assert_eq!(eval("2#101"), Ok(5));
+        assert_eq!(eval("36#z"), Ok(35));
+        assert!(eval("08").is_err());
+        assert!(eval("37#1").is_err());
+    }
+
+    #[test]
+    fn assignments_update_variables() {
+        let mut shell = Shell::new();
+        assert_eq!(evaluate("x = 5", &mut shell), Ok(5));
+        assert_eq!(evaluate("x += 2, x *= 3", &mut shell), Ok(21));
+        assert_eq!(evaluate("x++", &mut shell), Ok(21));
+        assert_eq!(evaluate("++x", &mut shell), Ok(23));
+        assert_eq!(evaluate("x--", &mut shell), Ok(23));
+        assert_eq!(shell.get_var("x").as_deref(), Some("22"));
+    }
+
+    #[test]
+    fn increments_wrap_around() {
+        let mut shell = Shell::new();
+        shell.set_var("x", i64::MAX.to_string());
+        assert_eq!(evaluate("x++", &mut shell), Ok(i64::MAX));
+        assert_eq!(shell.get_var("x"), Some(i64::MIN.to_string()));
+        assert_eq!(evaluate("--x", &mut shell), Ok(i64::MAX));
+    }
+
+    #[test]
+    fn short_circuits_skip_side_effects() {
+        let mut shell = Shell::new();
+        assert_eq!(evaluate("0 && (y = 1)", &mut shell), Ok(0));
+        assert_eq!(evaluate("1 || (y = 2)", &mut shell), Ok(1));
+        assert_eq!(evaluate("1 ? (z = 3) : (y = 4)", &mut shell), Ok(3));
+        assert_eq!(shell.get_var("y"), None);
+        assert_eq!(shell.get_var("z").as_deref(), Some("3"));
+    }
+
+    #[test]
+    fn variables_hold_expressions() {
+        let mut shell = Shell::new();
+        shell.set_var("a", "b + 1".into());
+        shell.set_var("b", "2".into());
+        assert_eq!(evaluate("a * 2", &mut shell), Ok(6));
+        assert_eq!(evaluate("unset + 1", &mut shell), Ok(1));
+    }
+
+    #[test]
+    fn self_referencing_variables_are_an_error() {
+        let mut shell = Shell::new();
+        shell.set_var("a", "a".into());
+        let error = evaluate("a", &mut shell).unwrap_err();
+        assert!(
+            error.contains("expression recursion level exceeded"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(eval("1 / 0"), Err("division by 0".into()));
+        assert_eq!(eval("2 ** -1"), Err("exponent less than 0".into()));
+        assert!(eval("1 +").is_err());
+        assert!(eval("(1").is_err());
+        assert!(eval("1 ? 2").is_err());
+        assert!(eval("1 2").is_err());
+    }
+}
@@ -0,0 +1,451 @@
+use std::fmt;
+use std::rc::Rc;
+
+/// A sequence of and-or lists separated by `;`, `&` or newlines, run one
+/// after another.
+#[derive(Debug, Default)]
+pub struct List(pub Vec<AndOr>);
+
+/// Pipelines chained with `&&` and `||`, evaluated left to right with
+/// short-circuiting on the exit status of the previous pipeline.
+#[derive(Debug)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+    /// Whether it ended with `&`, to run as a background job.
+    pub background: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connector {
+    /// `&&`: run the next pipeline only if the previous one succeeded.
+    And,
+    /// `||`: run the next pipeline only if the previous one failed.
+    Or,
+}
+
+/// Commands connected by `|`.
+#[derive(Debug)]
+pub struct Pipeline {
+    pub commands: Vec<Command>,
+}
+
+/// One stage of a pipeline.
+#[derive(Debug, Clone)]
+pub enum Command {
+    Simple(SimpleCommand),
+    /// A compound command with the redirections that follow it, which apply
+    /// to everything it runs. Shared so that function bodies and forked
+    /// stages need no copy.
+    Compound(Rc<CompoundCommand>, Vec<Redirect>),
+    /// `name() body` or `function name body`, which defines the function
+    /// when run. The body is always a compound command.
+    Function(String, Rc<Command>),
+}
+
+#[derive(Debug)]
+pub enum CompoundCommand {
+    /// `{ list; }`, run in the current shell.
+    Group(List),
+    /// `( list )`, run in a forked copy of the shell.
+    Subshell(List),
+    /// `if list; then list; [elif list; then list;]... [else list;] fi`: the
+    /// body of the first branch whose condition succeeds runs.
+    If {
+        branches: Vec<(List, List)>,
+        otherwise: Option<List>,
+    },
+    /// `while list; do list; done`, or `until` when the condition has to
+    /// fail for the body to run.
+    Loop {
+        until: bool,
+        condition: List,
+        body: List,
+    },
+    /// `for name in words; do list; done`, over the positional parameters
+    /// when there is no `in`.
+    For {
+        name: String,
+        words: Option<Vec<String>>,
+        body: List,
+    },
+    /// `for ((init; condition; step)); do list; done`, with arithmetic
+    /// expressions that are expanded each time they are evaluated.
+    ArithmeticFor {
+        init: String,
+        condition: String,
+        step: String,
+        body: List,
+    },
+    /// `case word in pattern) list;; ... esac`
+    Case { word: String, items: Vec<CaseItem> },
+}
+
+/// The patterns of one `case` branch and what it runs.
+#[derive(Debug)]
+pub struct CaseItem {
+    pub patterns: Vec<String>,
+    pub body: List,
+}
+
+/// A command name with its arguments, preceded by `NAME=value` assignments.
+///
+/// With no words the assignments set shell variables; otherwise they only
+/// apply to the environment of that one command.
+///
+/// Words and redirection targets are kept as they appear in the input, quotes
+/// included; they are expanded each time the command runs.
+#[derive(Debug, Default, Clone)]
+pub struct SimpleCommand {
+    pub assignments: Vec<(String, String)>,
+    pub words: Vec<String>,
+    /// Redirections, applied in order after any pipe.
+    pub redirects: Vec<Redirect>,
+}
+
+/// A redirection of one file descriptor of a command, such as `2>>log`.
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    pub fd: i32,
+    pub op: RedirectOp,
+    pub target: String,
+    /// The delimiter word of a here-document, as written, whose body is the
+    /// target.
+    pub delimiter: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectOp {
+    /// `<`: open the target for reading.
+    Input,
+    /// `>`: create or truncate the target, unless `noclobber` forbids it.
+    Output,
+    /// `>|`: like `>`, but ignoring `noclobber`.
+    Clobber,
+    /// `>>`: append to the target.
+    Append,
+    /// `<>`: open the target for reading and writing.
+    ReadWrite,
+    /// `<&`: duplicate the descriptor named by the target, or close with `-`.
+    DuplicateInput,
+    /// `>&`: like `<&`, for output.
+    DuplicateOutput,
+    /// `<<`: the target is the body of a here-document, which gets parameter
+    /// and command expansion but no quote removal.
+    HereDoc,
+    /// A here-document whose delimiter was quoted, used exactly as written.
+    LiteralHereDoc,
+    /// `<<<`: the expanded target followed by a newline.
+    HereString,
+}
+
+impl RedirectOp {
+    /// The descriptor redirected when the operator has no number before it.
+    pub fn default_fd(self) -> i32 {
+        match self {
+            RedirectOp::Input
+            | RedirectOp::ReadWrite
+            | RedirectOp::DuplicateInput
+            | RedirectOp::HereDoc
+            | RedirectOp::LiteralHereDoc
+            | RedirectOp::HereString => 0,
+            _ => 1,
+        }
+    }
+}
+
+impl std::fmt::Display for RedirectOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let op = match self {
+            RedirectOp::Input => "<",
+            RedirectOp::Output => ">",
+            RedirectOp::Clobber => ">|",
+            RedirectOp::Append => ">>",
+            RedirectOp::ReadWrite => "<>",
+            RedirectOp::DuplicateInput => "<&",
+            RedirectOp::DuplicateOutput => ">&",
+            RedirectOp::HereDoc | RedirectOp::LiteralHereDoc => "<<",
+            RedirectOp::HereString => "<<<",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+// The textual forms below describe jobs in listings such as `jobs`. With
+// `{:#}`, a command is laid out over several lines like `type` shows
+// function bodies.
+
+impl List {
+    /// Writes the and-or lists on one line, or one per line at `indent` levels
+    /// deep. The last one only gets a `;` after it if `terminated`.
+    fn write(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        indent: Option<usize>,
+        terminated: bool,
+    ) -> fmt::Result {
+        for (i, and_or) in self.0.iter().enumerate() {
+            let last = i + 1 == self.0.len();
+            if let Some(indent) = indent {
+                write!(f, "{:width$}", "", width = indent * 4)?;
+            }
+            and_or.write(f, indent)?;
+            if and_or.background {
+                write!(f, " &")?;
+            } else if !last || terminated {
+                write!(f, ";")?;
+            }
+            if indent.is_some() {
+                and_or.write_heredocs(f)?;
+            }
+            if !last {
+                match indent {
+                    Some(_) => writeln!(f)?,
+                    None => write!(f, " ")?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AndOr {
+    fn write(&self, f: &mut fmt::Formatter<'_>, indent: Option<usize>) -> fmt::Result {
+        self.first.write(f, indent)?;
+        for (connector, pipeline) in &self.rest {
+            match connector {
+                Connector::And => write!(f, " && ")?,
+                Connector::Or => write!(f, " || ")?,
+            }
+            pipeline.write(f, indent)?;
+        }
+        Ok(())
+    }
+}
+
+impl AndOr {
+    /// Writes the bodies of the here-documents started on the line of the
+    /// and-or list, each on the lines after it and closed by its delimiter.
+    fn write_heredocs(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pipelines = std::iter::once(&self.first).chain(self.rest.iter().map(|(_, p)| p));
+        for command in pipelines.flat_map(|pipeline| &pipeline.commands) {
+            let redirects = match command {
+                Command::Simple(command) => &command.redirects,
+                Command::Compound(_, redirects) => redirects,
+                Command::Function(..) => continue,
+            };
+            for redirect in redirects {
+                if let Some(delimiter) = &redirect.delimiter {
+                    write!(f, "\n{}{}", redirect.target, super::unquote(delimiter))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for AndOr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, None)
+    }
+}
+
+impl Pipeline {
+    fn write(&self, f: &mut fmt::Formatter<'_>, indent: Option<usize>) -> fmt::Result {
+        for (i, command) in self.commands.iter().enumerate() {
+            if i > 0 {
+                write!(f, " | ")?;
+            }
+            command.write(f, indent)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, None)
+    }
+}
+
+impl Command {
+    fn write(&self, f: &mut fmt::Formatter<'_>, indent: Option<usize>) -> fmt::Result {
+        match self {
+            Command::Simple(command) => write!(f, "{}", command),
+            Command::Compound(compound, redirects) => {
+                compound.write(f, indent)?;
+                for redirect in redirects {
+                    match redirect.op {
+                        RedirectOp::HereDoc | RedirectOp::LiteralHereDoc => {
+                            write!(f, " {}", redirect)?
+                        }
+                        op if redirect.fd == op.default_fd() => {
+                            write!(f, " {} {}", op, redirect.target)?
+                        }
+                        op => write!(f, " {}{} {}", redirect.fd, op, redirect.target)?,
+                    }
+                }
+                Ok(())
+            }
+            Command::Function(name, body) => {
+                write!(f, "{} () ", name)?;
+                if indent.is_some() {
+                    writeln!(f)?;
+                }
+                body.write(f, indent)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, f.alternate().then_some(0))
+    }
+}
+
+impl CompoundCommand {
+    fn write(&self, f: &mut fmt::Formatter<'_>, indent: Option<usize>) -> fmt::Result {
+        // Where a nested list starts and how far its closing word is indented.
+        let (inner, pad, newline) = match indent {
+            Some(indent) => (Some(indent + 1), indent * 4, "\n"),
+            None => (None, 0, " "),
+        };
+        let end = |f: &mut fmt::Formatter<'_>, word: &str| match indent {
+            Some(_) => write!(f, "\n{:pad$}{}", "", word),
+            None => write!(f, " {}", word),
+        };
+
+        match self {
+            CompoundCommand::Group(list) => {
+                write!(f, "{{ {}", if indent.is_some() { "\n" } else { "" })?;
+                list.write(f, inner, indent.is_none())?;
+                end(f, "}")
+            }
+            CompoundCommand::Subshell(list) => {
+                write!(f, "( ")?;
+                list.write(f, None, false)?;
+                write!(f, " )")
+            }
+            CompoundCommand::If {
+                branches,
+                otherwise,
+            } => {
+                for (i, (condition, body)) in branches.iter().enumerate() {
+                    if i > 0 {
+                        end(f, "elif ")?;
+                    } else {
+                        write!(f, "if ")?;
+                    }
+                    condition.write(f, None, true)?;
+                    write!(f, " then{}", newline)?;
+                    body.write(f, inner, true)?;
+                }
+                if let Some(otherwise) = otherwise {
+                    end(f, "else")?;
+                    write!(f, "{}", newline)?;
+                    otherwise.write(f, inner, true)?;
+                }
+                end(f, "fi")
+            }
+            CompoundCommand::Loop {
+                until,
+                condition,
+                body,
+            } => {
+                write!(f, "{} ", if *until { "until" } else { "while" })?;
+                condition.write(f, None, true)?;
+                write!(f, " do{}", newline)?;
+                body.write(f, inner, true)?;
+                end(f, "done")
+            }
+            CompoundCommand::For { name, words, body } => {
+                write!(f, "for {} in ", name)?;
+                match words {
+                    Some(words) => write!(f, "{};", words.join(" "))?,
+                    None => write!(f, "\"$@\";")?,
+                }
+                end(f, "do")?;
+                write!(f, "{}", newline)?;
+                body.write(f, inner, true)?;
+                end(f, "done")
+            }
+            CompoundCommand::ArithmeticFor {
+                init,
+                condition,
+                step,
+                body,
+            } => {
+                write!(f, "for (({}; {}; {}))", init, condition, step)?;
+                if indent.is_none() {
+                    write!(f, ";")?;
+                }
+                end(f, "do")?;
+                write!(f, "{}", newline)?;
+                body.write(f, inner, true)?;
+                end(f, "done")
+            }
+            CompoundCommand::Case { word, items } => {
+                write!(f, "case {} in ", word)?;
+                for (i, item) in items.iter().enumerate() {
+                    match indent {
+                        Some(indent) => write!(f, "\n{:width$}", "", width = (indent + 1) * 4)?,
+                        None if i > 0 => write!(f, " ")?,
+                        None => {}
+                    }
+                    write!(f, "{})", item.patterns.join(" | "))?;
+                    match indent {
+                        Some(indent) => {
+                            if !item.body.0.is_empty() {
+                                writeln!(f)?;
+                                item.body.write(f, Some(indent + 2), false)?;
+                            }
+                            write!(f, "\n{:width$};;", "", width = (indent + 1) * 4)?;
+                        }
+                        None => {
+                            if !item.body.0.is_empty() {
+                                write!(f, " ")?;
+                                item.body.write(f, None, false)?;
+                            }
+                            write!(f, ";;")?;
+                        }
+                    }
+                }
+                end(f, "esac")
+            }
+        }
+    }
+}
+
+impl fmt::Display for SimpleCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let assignments = self
+            .assignments
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value));
+        let redirects = self.redirects.iter().map(Redirect::to_string);
+        let parts: Vec<String> = assignments
+            .chain(self.words.iter().cloned())
+            .chain(redirects)
+            .collect();
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+impl fmt::Display for Redirect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.op {
+            // The body of a here-document does not fit on the line, so only
+            // the multi-line form of the command it is in shows it.
+            RedirectOp::HereDoc | RedirectOp::LiteralHereDoc => {
+                let delimiter = self.delimiter.as_deref().unwrap_or_default();
+                match self.fd {
+                    0 => write!(f, "{}{}", self.op, delimiter),
+                    fd => write!(f, "{}{}{}", fd, self.op, delimiter),
+                }
+            }
+            op if self.fd == op.default_fd() => write!(f, "{}{}", op, self.target),
+            op => write!(f, "{}{}{}", self.fd, op, self.target),
+        }
+    }
+}
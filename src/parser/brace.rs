@@ -0,0 +1,192 @@
+use super::ParseState;
+
+/// Performs brace expansion on a raw word, before any other expansion:
+/// `a{b,c}d` becomes `abd acd`, and `{1..5..2}` or `{a..e}` become
+/// sequences. Braces that are quoted, escaped or part of `${...}` are left
+/// alone.
+pub fn expand_braces(word: &str) -> Vec<String> {
+    let chars: Vec<char> = word.chars().collect();
+    let active = active_chars(&chars);
+    expand(&chars, &active)
+}
+
+fn expand(chars: &[char], active: &[bool]) -> Vec<String> {
+    for open in 0..chars.len() {
+        if !active[open] || chars[open] != '{' {
+            continue;
+        }
+
+        let mut depth = 0;
+        let mut commas = Vec::new();
+        let mut close = None;
+        for i in open + 1..chars.len() {
+            if !active[i] {
+                continue;
+            }
+            match chars[i] {
+                '{' => depth += 1,
+                '}' if depth == 0 => {
+                    close = Some(i);
+                    break;
+                }
+                '}' => depth -= 1,
+                ',' if depth == 0 => commas.push(i),
+                _ => {}
+            }
+        }
+        let Some(close) = close else {
+            continue;
+        };
+
+        let alternatives = if commas.is_empty() {
+            let body: String = chars[open + 1..close].iter().collect();
+            match sequence(&body) {
+                Some(items) => items,
+                None => continue,
+            }
+        } else {
+            let mut bounds = vec![open];
+            bounds.extend(&commas);
+            bounds.push(close);
+            bounds
+                .windows(2)
+                .flat_map(|w| expand(&chars[w[0] + 1..w[1]], &active[w[0] + 1..w[1]]))
+                .collect()
+        };
+
+        let prefix: &String = &chars[..open].iter().collect();
+        let suffixes = expand(&chars[close + 1..], &active[close + 1..]);
+        return alternatives
+            .iter()
+            .flat_map(|alternative| {
+                suffixes
+                    .iter()
+                    .map(move |suffix| format!("{}{}{}", prefix, alternative, suffix))
+            })
+            .collect();
+    }
+
+    vec![chars.iter().collect()]
+}
+
+/// Marks the characters that can take part in brace expansion: those outside
+/// quotes, escapes and `$`-expansions.
+fn active_chars(chars: &[char]) -> Vec<bool> {
+    let mut active = vec![false; chars.len()];
+    let mut state = ParseState::Normal;
+    let mut i = 0;
+    while i < chars.len() {
+        match (state, chars[i]) {
+            (ParseState::Normal | ParseState::InDoubleQuote, '\\') => i += 1,
+            (ParseState::Normal, '\'') => state = ParseState::InSingleQuote,
+            (ParseState::Normal, '"') => state = ParseState::InDoubleQuote,
+            (ParseState::InSingleQuote, '\'') | (ParseState::InDoubleQuote, '"') => {
+                state = ParseState::Normal
+            }
+            (ParseState::Normal | ParseState::InDoubleQuote, '$')
+                if matches!(chars.get(i + 1), Some('{' | '(')) =>
+            {
+                i = skip_enclosed(chars, i + 1);
+            }
+            (ParseState::Normal | ParseState::InDoubleQuote, '`') => {
+                i += 1;
+                while i < chars.len() && chars[i] != '`' {
+                    i += if chars[i] == '\\' { 2 } else { 1 };
+                }
+            }
+            (ParseState::Normal, _) => active[i] = true,
+            _ => {}
+        }
+        i += 1;
+    }
+    active
+}
+
+/// Returns the index of the bracket closing the one at `open`.
+fn skip_enclosed(chars: &[char], open: usize) -> usize {
+    let close = if chars[open] == '{' { '}' } else { ')' };
+    let mut depth = 0;
+    for (i, &ch) in chars.iter().enumerate().skip(open) {
+        if ch == chars[open] {
+            depth += 1;
+        } else if ch == close {
+            depth -= 1;
+            if depth == 0 {
+                return i;
+            }
+        }
+    }
+    chars.len()
+}
+
+/// Expands the body of a `{start..end}` or `{start..end..step}` sequence.
+fn sequence(body: &str) -> Option<Vec<String>> {
+    let parts: Vec<&str> = body.split("..").collect();
+    let (start, end, step) = match parts[..] {
+        [start, end] => (start, end, 1),
+        [start, end, step] => (start, end, step.parse::<i64>().ok()?),
+        _ => return None,
+    };
+    let step = step.unsigned_abs().max(1) as usize;
+
+    if let (Ok(first), Ok(last)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        // A leading zero on either end pads every number to the same width.
+        let padded = |s: &str| {
+            let digits = s.trim_start_matches(['-', '+']);
+            digits.len() > 1 && digits.starts_with('0')
+        };
+        let width = if padded(start) || padded(end) {
+            start.len().max(end.len())
+        } else {
+            0
+        };
+        let numbers: Vec<i64> = if first <= last {
+            (first..=last).step_by(step).collect()
+        } else {
+            (last..=first).rev().step_by(step).collect()
+        };
+        return Some(
+            numbers
+                .into_iter()
+                .map(|n| {
+                    if n < 0 {
+                        format!(
+                            "-{:0width$}",
+                            n.unsigned_abs(),
+                            width = width.saturating_sub(1)
+                        )
+                    } else {
+                        format!("{:0width$}", n, width = width)
+                    }
+                })
+                .collect(),
+        );
+    }
+
+    let mut start_chars = start.chars();
+    let mut end_chars = end.chars();
+    match (
+        start_chars.next(),
+        start_chars.next(),
+        end_chars.next(),
+        end_chars.next(),
+    ) {
+        (Some(first), None, Some(last), None)
+            if first.is_ascii_alphabetic() && last.is_ascii_alphabetic() =>
+        {
+            let (first, last) = (first as u8, last as u8);
+            let letters: Vec<u8> = if first <= last {
+                (first..=last).step_by(step).collect()
+            } else {
+                (last..=first).rev().step_by(step).collect()
+            };
+            Some(
+                letters
+                    .into_iter()
+                    .map(|c| (c as char).to_string())
+                    .collect(),
+            )
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,844 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+use super::brace;
+use super::glob::{self, GlobOptions};
+use super::pattern::Pattern;
+use super::ParseState;
+use crate::shell::Shell;
+
+/// A field being built during expansion. Each character remembers whether it
+/// was quoted, since only unquoted text is subject to later processing.
+type Field = Vec<(char, bool)>;
+
+/// The value of a parameter before it is added to the word.
+enum Value {
+    /// A plain parameter such as `$HOME`; `None` when unset.
+    Single(Option<String>),
+    /// `$@` or `${name[@]}`: one field per element, even when quoted.
+    Each(Vec<String>),
+    /// `$*` or `${name[*]}`: elements joined by the first `IFS` character
+    /// when quoted.
+    Joined(Vec<String>),
+    /// An already expanded word, such as the default in `${name:-word}`.
+    Word(Vec<Field>),
+}
+
+impl Value {
+    fn is_set(&self) -> bool {
+        match self {
+            Value::Single(value) => value.is_some(),
+            Value::Each(elements) | Value::Joined(elements) => !elements.is_empty(),
+            Value::Word(_) => true,
+        }
+    }
+
+    fn is_null(&self) -> bool {
+        match self {
+            Value::Single(value) => value.as_deref().map_or(true, str::is_empty),
+            Value::Each(elements) | Value::Joined(elements) => {
+                elements.iter().all(String::is_empty)
+            }
+            Value::Word(fields) => fields.is_empty(),
+        }
+    }
+
+    /// Applies `f` to the value, or to each element of an array.
+    fn map(self, f: impl Fn(&str) -> String) -> Value {
+        match self {
+            Value::Single(value) => Value::Single(value.map(|v| f(&v))),
+            Value::Each(elements) => Value::Each(elements.iter().map(|e| f(e)).collect()),
+            Value::Joined(elements) => Value::Joined(elements.iter().map(|e| f(e)).collect()),
+            word => word,
+        }
+    }
+}
+
+/// Expands the raw words of a command into the arguments it is run with:
+/// braces and tildes are expanded, parameters and commands are substituted,
+/// unquoted results are split into fields, pathname patterns are matched
+/// against the filesystem and quotes are removed.
+pub fn expand_words(words: &[String], shell: &mut Shell) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    for word in words.iter().flat_map(|word| brace::expand_braces(word)) {
+        let fields = Expander::new(&word, shell).expand()?;
+        for field in fields {
+            if !glob::has_wildcards(&field) {
+                args.push(to_string(field));
+                continue;
+            }
+
+            let options = GlobOptions {
+                dotglob: shell.shopt("dotglob"),
+                globstar: shell.shopt("globstar"),
+            };
+            let matches = glob::glob(&field, &options);
+            if !matches.is_empty() {
+                args.extend(matches);
+            } else if shell.shopt("failglob") {
+                return Err(format!("no match: {}", word));
+            } else if !shell.shopt("nullglob") {
+                args.push(to_string(field));
+            }
+        }
+    }
+    Ok(args)
+}
+
+/// Expands a word that must stay a single word, such as a redirection target.
+pub fn expand_word(word: &str, shell: &mut Shell) -> Result<String, String> {
+    let mut fields = Expander::new(word, shell).expand()?;
+    match fields.len() {
+        1 => Ok(to_string(fields.remove(0))),
+        _ => Err(format!("{}: ambiguous redirect", word)),
+    }
+}
+
+/// Expands the value of a `NAME=value` assignment, which is never split
+/// into fields and also has tildes expanded after each `:`.
+pub fn expand_assignment(value: &str, shell: &mut Shell) -> Result<String, String> {
+    let mut expander = Expander::new(value, shell);
+    expander.split = false;
+    expander.assignment = true;
+    let fields = expander.expand()?;
+    Ok(fields
+        .into_iter()
+        .map(to_string)
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+/// Expands the body of a here-document as if it were inside double quotes,
+/// except that `"` has no special meaning.
+pub fn expand_heredoc(body: &str, shell: &mut Shell) -> Result<String, String> {
+    let mut expander = Expander::new(body, shell);
+    expander.state = ParseState::InDoubleQuote;
+    expander.heredoc = true;
+    let fields = expander.expand()?;
+    Ok(fields
+        .into_iter()
+        .map(to_string)
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+/// Whether `text` matches the shell pattern `word`, as in a `case` branch.
+/// Quoted parts of the pattern only match themselves.
+pub fn matches_pattern(text: &str, word: &str, shell: &mut Shell) -> Result<bool, String> {
+    Ok(Expander::new(word, shell).pattern(word)?.matches(text))
+}
+
+/// The expression of `$((expression))`, given the text between the outer
+/// parentheses, if it is one parenthesized group rather than a command
+/// substitution of a subshell like `$( (cmd) )`.
+fn arithmetic_body(program: &str) -> Option<&str> {
+    let inner = program.strip_prefix('(')?.strip_suffix(')')?;
+    let mut depth = 0;
+    for ch in inner.chars() {
+        match ch {
+            '(' => depth += 1,
+            ')' if depth == 0 => return None,
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    (depth == 0).then_some(inner)
+}
+
+fn to_string(field: Field) -> String {
+    field.into_iter().map(|(ch, _)| ch).collect()
+}
+
+struct Expander<'a, 'b> {
+    shell: &'a mut Shell,
+    chars: Peekable<Chars<'b>>,
+    state: ParseState,
+    fields: Vec<Field>,
+    current: Field,
+    /// Whether `current` is a field even if it's empty, e.g. after `""`.
+    started: bool,
+    /// Whether unquoted expansions are split into fields on `IFS`.
+    split: bool,
+    /// Whether the word is an assignment value, where `:` separates paths.
+    assignment: bool,
+    /// Whether the next character starts a tilde prefix.
+    tilde: bool,
+    /// Whether the word is a here-document body, where `"` is literal.
+    heredoc: bool,
+}
+
+impl<'a, 'b> Expander<'a, 'b> {
+    fn new(word: &'b str, shell: &'a mut Shell) -> Self {
+        Expander {
+            shell,
+            chars: word.chars().peekable(),
+            state: ParseState::Normal,
+            fields: Vec::new(),
+            current: Vec::new(),
+            started: false,
+            split: true,
+            assignment: false,
+            tilde: true,
+            heredoc: false,
+        }
+    }
+
+    fn expand(mut self) -> Result<Vec<Field>, String> {
+        while let Some(ch) = self.chars.next() {
+            self.state = match self.state {
+                ParseState::Normal => self.handle_normal(ch)?,
+                ParseState::InSingleQuote => self.handle_in_single_quote(ch),
+                ParseState::InDoubleQuote => self.handle_in_double_quote(ch)?,
+            }
+        }
+        self.end_field();
+        Ok(self.fields)
+    }
+
+    fn handle_normal(&mut self, ch: char) -> Result<ParseState, String> {
+        let tilde = std::mem::replace(&mut self.tilde, false);
+        match ch {
+            '~' if tilde => self.expand_tilde(),
+            ':' if self.assignment => {
+                self.current.push((ch, false));
+                self.tilde = true;
+            }
+            '\\' => {
+                if let Some(escaped) = self.chars.next() {
+                    self.current.push((escaped, true));
+                }
+            }
+            '\'' => {
+                self.started = true;
+                return Ok(ParseState::InSingleQuote);
+            }
+            '"' => {
+                self.started = true;
+                return Ok(ParseState::InDoubleQuote);
+            }
+            '$' => self.expand_dollar(false)?,
+            '`' => {
+                let program = self.read_backquoted()?;
+                self.substitute(&program, false)?;
+            }
+            _ => self.current.push((ch, false)),
+        }
+        Ok(ParseState::Normal)
+    }
+
+    fn handle_in_single_quote(&mut self, ch: char) -> ParseState {
+        if ch == '\'' {
+            ParseState::Normal
+        } else {
+            self.current.push((ch, true));
+            ParseState::InSingleQuote
+        }
+    }
+
+    fn handle_in_double_quote(&mut self, ch: char) -> Result<ParseState, String> {
+        match ch {
+            '"' if !self.heredoc => return Ok(ParseState::Normal),
+            '\\' => match self.chars.peek() {
+                Some(&c) if matches!(c, '\\' | '$' | '`') || (c == '"' && !self.heredoc) => {
+                    let escaped = self.chars.next().unwrap();
+                    self.current.push((escaped, true));
+                }
+                Some('\n') => {
+                    // A backslash-newline is a line continuation.
+                    self.chars.next();
+                }
+                _ => self.current.push(('\\', true)),
+            },
+            '$' => self.expand_dollar(true)?,
+            '`' => {
+                let program = self.read_backquoted()?;
+                self.substitute(&program, true)?;
+            }
+            _ => self.current.push((ch, true)),
+        }
+        Ok(ParseState::InDoubleQuote)
+    }
+
+    /// Replaces `~`, `~user`, `~+` or `~-` at the start of a word with the
+    /// directory it names. The prefix is left alone if it is quoted or names
+    /// no known user.
+    fn expand_tilde(&mut self) {
+        let mut ahead = self.chars.clone();
+        let mut prefix = String::new();
+        while let Some(c) = ahead.next_if(|&c| c != '/' && !(self.assignment && c == ':')) {
+            prefix.push(c);
+        }
+
+        let dir = if prefix.contains(['\\', '\'', '"', '$', '`']) {
+            None
+        } else {
+            match prefix.as_str() {
+                "" => self.shell.get_var("HOME").or_else(|| home_dir(None)),
+                "+" => self.shell.get_var("PWD"),
+                "-" => self.shell.get_var("OLDPWD"),
+                user => home_dir(Some(user)),
+            }
+        };
+        match dir {
+            Some(dir) => {
+                for _ in prefix.chars() {
+                    self.chars.next();
+                }
+                // The directory is not split or matched against files.
+                self.push_str(&dir, true);
+            }
+            None => self.current.push(('~', false)),
+        }
+    }
+
+    /// Expands the parameter following a `$`.
+    fn expand_dollar(&mut self, quoted: bool) -> Result<(), String> {
+        let value = match self.chars.peek().copied() {
+            Some('{') => {
+                self.chars.next();
+                let body = self
+                    .read_enclosed('{', '}')
+                    .ok_or("unexpected EOF while looking for matching `}'")?;
+                self.parameter(&body, quoted)?
+            }
+            Some('(') => {
+                self.chars.next();
+                let rest: String = self.chars.clone().collect();
+                let end = super::Parser::substitution_end(&rest)
+                    .ok_or("unexpected EOF while looking for matching `)'")?;
+                let program = rest[..end].to_string();
+                for _ in rest[..=end].chars() {
+                    self.chars.next();
+                }
+                if let Some(expression) = arithmetic_body(&program) {
+                    return self.arithmetic(expression, quoted);
+                }
+                return self.substitute(&program, quoted);
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                let mut name = String::new();
+                while let Some(c) = self
+                    .chars
+                    .next_if(|c| c.is_ascii_alphanumeric() || *c == '_')
+                {
+                    name.push(c);
+                }
+                self.lookup(&name)
+            }
+            Some(c @ ('0'..='9' | '?' | '$' | '!' | '#' | '@' | '*')) => {
+                self.chars.next();
+                self.lookup(&c.to_string())
+            }
+            _ => {
+                self.current.push(('$', quoted));
+                return Ok(());
+            }
+        };
+        self.push_value(value, quoted);
+        Ok(())
+    }
+
+    /// Reads the body of `${...}` up to its matching bracket.
+    fn read_enclosed(&mut self, open: char, close: char) -> Option<String> {
+        let mut body = String::new();
+        let mut depth = 1;
+        let mut state = ParseState::Normal;
+        while let Some(ch) = self.chars.next() {
+            state = match (state, ch) {
+                (ParseState::Normal, c) if c == open => {
+                    depth += 1;
+                    ParseState::Normal
+                }
+                (ParseState::Normal, c) if c == close => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(body);
+                    }
+                    ParseState::Normal
+                }
+                (ParseState::Normal | ParseState::InDoubleQuote, '\\') => {
+                    body.push(ch);
+                    if let Some(escaped) = self.chars.next() {
+                        body.push(escaped);
+                    }
+                    continue;
+                }
+                (ParseState::Normal, '\'') => ParseState::InSingleQuote,
+                (ParseState::Normal, '"') => ParseState::InDoubleQuote,
+                (ParseState::InSingleQuote, '\'') | (ParseState::InDoubleQuote, '"') => {
+                    ParseState::Normal
+                }
+                (state, _) => state,
+            };
+            body.push(ch);
+        }
+        None
+    }
+
+    /// Reads the body of `` `...` ``, removing the backslashes that escape
+    /// `$`, `` ` `` and `\` inside it.
+    fn read_backquoted(&mut self) -> Result<String, String> {
+        let mut body = String::new();
+        while let Some(ch) = self.chars.next() {
+            match ch {
+                '`' => return Ok(body),
+                '\\' => match self.chars.next() {
+                    Some(escaped @ ('$' | '`' | '\\')) => body.push(escaped),
+                    Some(escaped) => {
+                        body.push(ch);
+                        body.push(escaped);
+                    }
+                    None => body.push(ch),
+                },
+                _ => body.push(ch),
+            }
+        }
+        Err("unexpected EOF while looking for matching ``'".into())
+    }
+
+    /// Runs a command substitution and adds its output to the word.
+    fn substitute(&mut self, program: &str, quoted: bool) -> Result<(), String> {
+        let output = self.shell.substitute(program)?;
+        self.push_str(output.trim_end_matches('\n'), quoted);
+        Ok(())
+    }
+
+    /// Evaluates a `$((...))` expansion and adds its value to the word. A
+    /// bad expression ends a non-interactive shell, like `${name?}`.
+    fn arithmetic(&mut self, expression: &str, quoted: bool) -> Result<(), String> {
+        match super::arith::evaluate(expression, self.shell) {
+            Ok(value) => {
+                self.push_str(&value.to_string(), quoted);
+                Ok(())
+            }
+            Err(e) => {
+                self.shell.set_fatal_error();
+                Err(e)
+            }
+        }
+    }
+
+    /// Evaluates the body of a `${...}` expansion.
+    fn parameter(&mut self, body: &str, quoted: bool) -> Result<Value, String> {
+        let bad_substitution = || format!("${{{}}}: bad substitution", body);
+
+        // `${#name}` is the length of the value, or the number of elements.
+        if let Some(name) = body.strip_prefix('#').filter(|name| !name.is_empty()) {
+            let (param, "") = split_parameter(name).ok_or_else(bad_substitution)? else {
+                return Err(bad_substitution());
+            };
+            let length = match self.value_of(param).ok_or_else(bad_substitution)? {
+                Value::Single(value) => value.unwrap_or_default().chars().count(),
+                Value::Each(elements) | Value::Joined(elements) => elements.len(),
+                Value::Word(fields) => fields.len(),
+            };
+            return Ok(Value::Single(Some(length.to_string())));
+        }
+
+        let (param, op) = split_parameter(body).ok_or_else(bad_substitution)?;
+        let value = self.value_of(param).ok_or_else(bad_substitution)?;
+        if op.is_empty() {
+            return Ok(value);
+        }
+
+        let (colon, test) = match op.strip_prefix(':') {
+            Some(rest) => (true, rest),
+            None => (false, op),
+        };
+        if let Some(kind @ ('-' | '=' | '?' | '+')) = test.chars().next() {
+            let word = &test[1..];
+            let present = if colon {
+                !value.is_null()
+            } else {
+                value.is_set()
+            };
+            return match (kind, present) {
+                ('-', true) | ('=', true) | ('?', true) => Ok(value),
+                ('+', false) => Ok(Value::Single(None)),
+                ('-', false) | ('+', true) => Ok(Value::Word(self.expand_inner(word, quoted)?)),
+                ('=', false) => {
+                    if !is_name(param) {
+                        return Err(format!("${}: cannot assign in this way", param));
+                    }
+                    let assigned = self.expand_inner_string(word)?;
+                    self.shell.set_var(param, assigned.clone());
+                    Ok(Value::Single(Some(assigned)))
+                }
+                _ => {
+                    let message = self.expand_inner_string(word)?;
+                    self.shell.set_fatal_error();
+                    if !message.is_empty() {
+                        Err(format!("{}: {}", param, message))
+                    } else if colon {
+                        Err(format!("{}: parameter null or not set", param))
+                    } else {
+                        Err(format!("{}: parameter not set", param))
+                    }
+                }
+            };
+        }
+
+        if colon {
+            return self.substring(param, value, test);
+        }
+
+        if let Some(pattern) = op.strip_prefix('#') {
+            let (longest, pattern) = match pattern.strip_prefix('#') {
+                Some(pattern) => (true, pattern),
+                None => (false, pattern),
+            };
+            let pattern = self.pattern(pattern)?;
+            return Ok(value.map(|value| {
+                let chars: Vec<char> = value.chars().collect();
+                let len = pattern.match_prefix(&chars, longest).unwrap_or(0);
+                chars[len..].iter().collect()
+            }));
+        }
+
+        if let Some(pattern) = op.strip_prefix('%') {
+            let (longest, pattern) = match pattern.strip_prefix('%') {
+                Some(pattern) => (true, pattern),
+                None => (false, pattern),
+            };
+            let pattern = self.pattern(pattern)?;
+            return Ok(value.map(|value| {
+                let chars: Vec<char> = value.chars().collect();
+                let len = pattern.match_suffix(&chars, longest).unwrap_or(0);
+                chars[..chars.len() - len].iter().collect()
+            }));
+        }
+
+        if let Some(rest) = op.strip_prefix('/') {
+            let (mode, rest) = match rest.chars().next() {
+                Some(mode @ ('/' | '#' | '%')) => (mode, &rest[1..]),
+                _ => (' ', rest),
+            };
+            let (pattern, replacement) = split_unescaped(rest, '/');
+            let pattern = self.pattern(pattern)?;
+            let replacement = match replacement {
+                Some(replacement) => self.expand_inner_string(replacement)?,
+                None => String::new(),
+            };
+            return Ok(value.map(|value| replace(value, &pattern, &replacement, mode)));
+        }
+
+        Err(bad_substitution())
+    }
+
+    /// The value of a parameter name, possibly subscripted like `name[1]`.
+    fn value_of(&self, param: &str) -> Option<Value> {
+        let Some((name, index)) = param.strip_suffix(']').and_then(|p| p.split_once('[')) else {
+            return Some(self.lookup(param));
+        };
+        let elements = self.shell.get_array(name);
+        match index {
+            "@" => Some(Value::Each(elements)),
+            "*" => Some(Value::Joined(elements)),
+            index => {
+                let index: usize = index.trim().parse().ok()?;
+                Some(Value::Single(elements.get(index).cloned()))
+            }
+        }
+    }
+
+    /// `${name:offset}` and `${name:offset:length}`, counting in characters,
+    /// or in elements for `$@`, with negative numbers counting from the end.
+    fn substring(&mut self, param: &str, value: Value, spec: &str) -> Result<Value, String> {
+        let (offset, length) = split_unescaped(spec, ':');
+        let offset = self.integer(offset)?;
+        let length = length.map(|length| self.integer(length)).transpose()?;
+
+        let range = |len: usize| -> Result<std::ops::Range<usize>, String> {
+            let start = if offset < 0 {
+                // Counting back past the start leaves nothing.
+                match len.checked_sub(offset.unsigned_abs() as usize) {
+                    Some(start) => start,
+                    None => return Ok(0..0),
+                }
+            } else {
+                (offset as usize).min(len)
+            };
+            let end = match length {
+                None => len,
+                Some(length) if length < 0 => {
+                    let end = len as i64 + length;
+                    if end < start as i64 {
+                        return Err(format!("{}: substring expression < 0", length));
+                    }
+                    end as usize
+                }
+                Some(length) => (start + length as usize).min(len),
+            };
+            Ok(start..end)
+        };
+        // Slices of `$@` count `$0` as element zero.
+        let slice = |mut elements: Vec<String>| -> Result<Vec<String>, String> {
+            if matches!(param, "@" | "*") {
+                elements.insert(0, self.shell.get_var("0").unwrap_or_default());
+            }
+            Ok(elements[range(elements.len())?].to_vec())
+        };
+
+        Ok(match value {
+            Value::Single(value) => {
+                let chars: Vec<char> = value.unwrap_or_default().chars().collect();
+                Value::Single(Some(chars[range(chars.len())?].iter().collect()))
+            }
+            Value::Each(elements) => Value::Each(slice(elements)?),
+            Value::Joined(elements) => Value::Joined(slice(elements)?),
+            word => word,
+        })
+    }
+    /// Expands the word inside a `${...}` operator, such as the default in
+    /// `${name:-word}`, as if it stood where the expansion does.
+    fn expand_inner(&mut self, word: &str, quoted: bool) -> Result<Vec<Field>, String> {
+        let mut inner = Expander::new(word, self.shell);
+        inner.split = self.split && !quoted;
+        let mut fields = inner.expand()?;
+        if quoted {
+            for (_, was_quoted) in fields.iter_mut().flatten() {
+                *was_quoted = true;
+            }
+        }
+        Ok(fields)
+    }
+
+    fn expand_inner_string(&mut self, word: &str) -> Result<String, String> {
+        expand_assignment(word, self.shell)
+    }
+
+    /// Expands the pattern of a `${...}` operator, keeping quoted characters
+    /// literal.
+    fn pattern(&mut self, word: &str) -> Result<Pattern, String> {
+        let mut inner = Expander::new(word, self.shell);
+        inner.split = false;
+        let fields = inner.expand()?;
+        let mut chars = Vec::new();
+        for (i, field) in fields.into_iter().enumerate() {
+            if i > 0 {
+                chars.push((' ', true));
+            }
+            chars.extend(field);
+        }
+        Ok(Pattern::new(&chars))
+    }
+
+    /// Expands and evaluates a substring offset or length.
+    fn integer(&mut self, text: &str) -> Result<i64, String> {
+        let expanded = self.expand_inner_string(text)?;
+        let number = expanded.trim();
+        let number = number
+            .strip_prefix('(')
+            .and_then(|n| n.strip_suffix(')'))
+            .unwrap_or(number)
+            .trim();
+        if number.is_empty() {
+            return Ok(0);
+        }
+        number
+            .parse()
+            .map_err(|_| format!("{}: syntax error: operand expected", expanded.trim()))
+    }
+
+    fn lookup(&self, name: &str) -> Value {
+        match name {
+            "@" => Value::Each(self.shell.positional().to_vec()),
+            "*" => Value::Joined(self.shell.positional().to_vec()),
+            name => Value::Single(self.shell.get_var(name)),
+        }
+    }
+
+    fn push_value(&mut self, value: Value, quoted: bool) {
+        match value {
+            Value::Single(value) => self.push_str(&value.unwrap_or_default(), quoted),
+            Value::Each(elements) => {
+                if quoted && elements.is_empty() {
+                    // `"$@"` with no elements expands to no field at all.
+                    self.started = false;
+                }
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        self.end_field();
+                    }
+                    self.push_str(element, quoted);
+                }
+            }
+            Value::Joined(elements) if quoted => {
+                let separator = self.ifs().chars().next().map(String::from);
+                self.push_str(&elements.join(separator.as_deref().unwrap_or("")), true);
+            }
+            Value::Joined(elements) => {
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        self.end_field();
+                    }
+                    self.push_str(element, false);
+                }
+            }
+            Value::Word(fields) => {
+                for (i, field) in fields.into_iter().enumerate() {
+                    if i > 0 {
+                        self.end_field();
+                    }
+                    // An empty field only survives if it was quoted.
+                    self.started |= field.is_empty();
+                    self.push_chars(field);
+                }
+            }
+        }
+    }
+
+    /// Adds expanded text to the word, splitting it into fields on `IFS`
+    /// unless it was quoted.
+    fn push_str(&mut self, value: &str, quoted: bool) {
+        self.started |= quoted;
+        self.push_chars(value.chars().map(|ch| (ch, quoted)));
+    }
+
+    fn push_chars(&mut self, chars: impl IntoIterator<Item = (char, bool)>) {
+        let ifs = if self.split {
+            self.ifs()
+        } else {
+            String::new()
+        };
+        for (ch, quoted) in chars {
+            if quoted || !ifs.contains(ch) {
+                self.current.push((ch, quoted));
+            } else if ch.is_whitespace() {
+                self.end_field();
+            } else {
+                // Non-whitespace separators delimit fields even when empty.
+                self.started = true;
+                self.end_field();
+            }
+        }
+    }
+
+    fn end_field(&mut self) {
+        if !self.current.is_empty() || self.started {
+            self.fields.push(std::mem::take(&mut self.current));
+        }
+        self.started = false;
+    }
+
+    fn ifs(&self) -> String {
+        self.shell
+            .get_var("IFS")
+            .unwrap_or_else(|| " \t\n".to_string())
+    }
+}
+
+/// Looks up the home directory of `user`, or of the current user, in the
+/// passwd database.
+fn home_dir(user: Option<&str>) -> Option<String> {
+    let entry = match user {
+        Some(user) => {
+            let name = std::ffi::CString::new(user).ok()?;
+            unsafe { libc::getpwnam(name.as_ptr()) }
+        }
+        None => unsafe { libc::getpwuid(libc::getuid()) },
+    };
+    if entry.is_null() {
+        return None;
+    }
+    let dir = unsafe { std::ffi::CStr::from_ptr((*entry).pw_dir) };
+    Some(dir.to_string_lossy().into_owned())
+}
+
+/// Whether `s` is a valid variable name.
+pub fn is_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Splits the name of a parameter, with an optional `[index]`, from the
+/// operator that follows it in `${...}`.
+fn split_parameter(body: &str) -> Option<(&str, &str)> {
+    let first = body.chars().next()?;
+    let mut end = if first.is_ascii_digit() {
+        body.find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(body.len())
+    } else if first.is_ascii_alphabetic() || first == '_' {
+        body.find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(body.len())
+    } else if matches!(first, '?' | '$' | '#' | '@' | '*') {
+        1
+    } else {
+        return None;
+    };
+    if body[end..].starts_with('[') {
+        end += body[end..].find(']')? + 1;
+    }
+    Some(body.split_at(end))
+}
+
+/// Splits `text` at the first `separator` that is not escaped or quoted.
+fn split_unescaped(text: &str, separator: char) -> (&str, Option<&str>) {
+    let mut state = ParseState::Normal;
+    let mut chars = text.char_indices();
+    while let Some((i, ch)) = chars.next() {
+        state = match (state, ch) {
+            (ParseState::Normal, c) if c == separator => {
+                return (&text[..i], Some(&text[i + c.len_utf8()..]));
+            }
+            (ParseState::Normal | ParseState::InDoubleQuote, '\\') => {
+                chars.next();
+                state
+            }
+            (ParseState::Normal, '\'') => ParseState::InSingleQuote,
+            (ParseState::Normal, '"') => ParseState::InDoubleQuote,
+            (ParseState::InSingleQuote, '\'') | (ParseState::InDoubleQuote, '"') => {
+                ParseState::Normal
+            }
+            (state, _) => state,
+        };
+    }
+    (text, None)
+}
+
+/// `${name/pattern/replacement}`: `mode` is `/` to replace every match, `#`
+/// or `%` to anchor at the start or end, and anything else for the first.
+fn replace(value: &str, pattern: &Pattern, replacement: &str, mode: char) -> String {
+    let chars: Vec<char> = value.chars().collect();
+    match mode {
+        '#' => match pattern.match_prefix(&chars, true) {
+            Some(len) if len > 0 => replacement
+                .chars()
+                .chain(chars[len..].iter().copied())
+                .collect(),
+            _ => value.to_string(),
+        },
+        '%' => match pattern.match_suffix(&chars, true) {
+            Some(len) if len > 0 => chars[..chars.len() - len]
+                .iter()
+                .copied()
+                .chain(replacement.chars())
+                .collect(),
+            _ => value.to_string(),
+        },
+        _ => {
+            let mut replaced = String::new();
+            let mut i = 0;
+            while i < chars.len() {
+                match pattern.match_prefix(&chars[i..], true) {
+                    Some(len) if len > 0 => {
+                        replaced.push_str(replacement);
+                        i += len;
+                        if mode != '/' {
+                            replaced.extend(&chars[i..]);
+                            break;
+                        }
+                    }
+                    _ => {
+                        replaced.push(chars[i]);
+                        i += 1;
+                    }
+                }
+            }
+            replaced
+        }
+    }
+}
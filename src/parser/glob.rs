@@ -0,0 +1,145 @@
+use std::path::Path;
+
+use super::pattern::Pattern;
+
+/// The `shopt` settings that change how pathnames are matched.
+pub struct GlobOptions {
+    /// Let wildcards match names starting with `.`.
+    pub dotglob: bool,
+    /// Let a `**` path component match any number of directories.
+    pub globstar: bool,
+}
+
+/// Whether a field contains an unquoted `*`, `?` or `[`.
+pub fn has_wildcards(field: &[(char, bool)]) -> bool {
+    field
+        .iter()
+        .any(|&(ch, quoted)| !quoted && matches!(ch, '*' | '?' | '['))
+}
+
+/// Expands a field into the sorted list of existing paths it matches.
+pub fn glob(field: &[(char, bool)], options: &GlobOptions) -> Vec<String> {
+    let components: Vec<_> = field.split(|&(ch, _)| ch == '/').collect();
+    let mut matches = Vec::new();
+    match components.split_first() {
+        // An absolute path starts with an empty component.
+        Some((first, rest)) if first.is_empty() && !rest.is_empty() => {
+            glob_in(Path::new("/"), "/", rest, options, &mut matches)
+        }
+        _ => glob_in(Path::new("."), "", &components, options, &mut matches),
+    }
+    matches.sort();
+    matches.dedup();
+    matches
+}
+
+/// Matches `components` against the directory `dir`, whose path as the user
+/// wrote it is `prefix`.
+fn glob_in(
+    dir: &Path,
+    prefix: &str,
+    components: &[&[(char, bool)]],
+    options: &GlobOptions,
+    matches: &mut Vec<String>,
+) {
+    let Some((component, rest)) = components.split_first() else {
+        return;
+    };
+
+    // A trailing `/` only keeps directories. There is none to keep when
+    // nothing came before it, as after a leading `**`.
+    if component.is_empty() && rest.is_empty() {
+        if dir.is_dir() && !prefix.is_empty() {
+            matches.push(prefix.to_string());
+        }
+        return;
+    }
+
+    if !has_wildcards(component) {
+        let name: String = component.iter().map(|&(ch, _)| ch).collect();
+        let path = dir.join(&name);
+        descend(&path, format!("{}{}", prefix, name), rest, options, matches);
+        return;
+    }
+
+    let is_globstar = options.globstar && matches!(component, [('*', false), ('*', false)]);
+    if is_globstar {
+        // `**` matches zero or more directories, and every file at the end.
+        if rest.is_empty() {
+            // Zero directories leave the one `**` is in, as `d/`.
+            if !prefix.is_empty() {
+                matches.push(prefix.to_string());
+            }
+            for (path, name) in walk(dir, prefix, options) {
+                descend(&path, name, rest, options, matches);
+            }
+        } else {
+            glob_in(dir, prefix, rest, options, matches);
+            for (path, name) in walk(dir, prefix, options) {
+                if path.is_dir() {
+                    glob_in(&path, &format!("{}/", name), rest, options, matches);
+                }
+            }
+        }
+        return;
+    }
+
+    let pattern = Pattern::new(component);
+    let explicit_dot = matches!(component.first(), Some(('.', _)));
+    for (name, path) in entries(dir) {
+        if name.starts_with('.') && !explicit_dot && !options.dotglob {
+            continue;
+        }
+        if pattern.matches(&name) {
+            descend(&path, format!("{}{}", prefix, name), rest, options, matches);
+        }
+    }
+}
+
+/// Continues matching below `path` once one component has matched.
+fn descend(
+    path: &Path,
+    name: String,
+    rest: &[&[(char, bool)]],
+    options: &GlobOptions,
+    matches: &mut Vec<String>,
+) {
+    if rest.is_empty() {
+        if path.symlink_metadata().is_ok() {
+            matches.push(name);
+        }
+    } else if path.is_dir() {
+        glob_in(path, &format!("{}/", name), rest, options, matches);
+    }
+}
+
+/// Every file and directory below `dir`, recursively, for `**`.
+fn walk(dir: &Path, prefix: &str, options: &GlobOptions) -> Vec<(std::path::PathBuf, String)> {
+    let mut found = Vec::new();
+    for (name, path) in entries(dir) {
+        if name.starts_with('.') && !options.dotglob {
+            continue;
+        }
+        let display = format!("{}{}", prefix, name);
+        // Like bash, do not follow symlinks to directories.
+        let is_dir = path.symlink_metadata().is_ok_and(|m| m.is_dir());
+        found.push((path.clone(), display.clone()));
+        if is_dir {
+            found.extend(walk(&path, &format!("{}/", display), options));
+        }
+    }
+    found
+}
+
+fn entries(dir: &Path) -> Vec<(String, std::path::PathBuf)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            Some((name, entry.path()))
+        })
+        .collect()
+}
@@ -1,74 +1,527 @@
+pub mod alias;
+pub mod arith;
+pub mod ast;
+mod brace;
+pub mod expand;
+mod glob;
+pub mod pattern;
+
+use std::collections::BTreeMap;
 use std::iter::Peekable;
+use std::rc::Rc;
 use std::str::Chars;
+
+use ast::{
+    AndOr, CaseItem, Command, CompoundCommand, Connector, List, Pipeline, Redirect, RedirectOp,
+    SimpleCommand,
+};
+
 /// Represents the various states the parser can be in
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 enum ParseState {
     Normal,
     InSingleQuote,
     InDoubleQuote,
 }
 
+/// A lexical unit of the input: a word or an unquoted operator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Pipe,
+    And,
+    Or,
+    Semi,
+    /// `;;`, ending a `case` branch.
+    DoubleSemi,
+    Newline,
+    /// `&` at the end of an and-or list.
+    Amp,
+    LParen,
+    RParen,
+    /// The `((init; condition; step))` of an arithmetic `for`, with the
+    /// text between the parentheses.
+    Arithmetic(String),
+    /// `<`, `>`, `2>>`, `3>&`, ... with the descriptor written before it.
+    Redirect(Option<i32>, RedirectOp),
+    /// `&>` or, when appending, `&>>`: redirects both stdout and stderr.
+    RedirectAll(bool),
+    /// `<<word` or `<<-word`, with the raw delimiter word. The body is read
+    /// from the lines following the one the operator is on.
+    HereDoc {
+        fd: Option<i32>,
+        strip_tabs: bool,
+        delimiter: String,
+        body: String,
+    },
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{}", word),
+            Token::Pipe => write!(f, "|"),
+            Token::And => write!(f, "&&"),
+            Token::Or => write!(f, "||"),
+            Token::Semi => write!(f, ";"),
+            Token::DoubleSemi => write!(f, ";;"),
+            Token::Newline => write!(f, "newline"),
+            Token::Amp => write!(f, "&"),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::Arithmetic(expression) => write!(f, "(({}))", expression),
+            Token::Redirect(Some(fd), op) => write!(f, "{}{}", fd, op),
+            Token::Redirect(None, op) => write!(f, "{}", op),
+            Token::RedirectAll(append) => write!(f, "{}", if *append { "&>>" } else { "&>" }),
+            Token::HereDoc { strip_tabs, .. } => {
+                write!(f, "{}", if *strip_tabs { "<<-" } else { "<<" })
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 /// A `Parser` struct that holds the state and context for parsing operations.
 pub struct Parser<'a> {
-    args: Vec<String>,
+    tokens: Vec<Token>,
     current: String,
     state: ParseState,
     chars: Peekable<Chars<'a>>,
+    /// Indices of the here-document tokens whose bodies start on the next line.
+    pending_heredocs: Vec<usize>,
+    /// The delimiter of a here-document that the input ended before.
+    unterminated_heredoc: Option<String>,
 }
 
-pub struct ParseOutput {
-    pub commands: Vec<Vec<String>>,
-    pub out_target: Option<(String, bool)>,
-    pub err_target: Option<(String, bool)>,
-}
 impl Parser<'_> {
-    fn new(input: &str) -> Parser {
+    fn new(input: &str) -> Parser<'_> {
         Parser {
-            args: Vec::new(),
+            tokens: Vec::new(),
             current: String::new(),
             state: ParseState::Normal,
             chars: input.trim().chars().peekable(),
+            pending_heredocs: Vec::new(),
+            unterminated_heredoc: None,
         }
     }
 
-    pub fn parse(input: &str) -> Result<ParseOutput, String> {
+    /// Parses `input` into a list of commands, expanding `aliases` first.
+    pub fn parse(input: &str, aliases: &BTreeMap<String, String>) -> Result<List, String> {
         let mut parser = Parser::new(input);
-        while let Some(ch) = parser.chars.next() {
-            parser.state = match parser.state {
-                ParseState::Normal => parser.handle_normal(ch)?,
-                ParseState::InSingleQuote => parser.handle_in_single_quote(ch),
-                ParseState::InDoubleQuote => parser.handle_in_double_quote(ch)?,
+        parser.tokenize()?;
+        if let Some(delimiter) = &parser.unterminated_heredoc {
+            eprintln!(
+                "warning: here-document delimited by end-of-file (wanted `{}')",
+                delimiter
+            );
+        }
+
+        let tokens = alias::expand_aliases(parser.tokens, aliases)?;
+        let mut tokens = tokens.into_iter().peekable();
+        let list = parse_list(&mut tokens)?;
+        if let Some(token) = tokens.next() {
+            return Err(unexpected(Some(&token)));
+        }
+        Ok(list)
+    }
+
+    /// Whether `input` is ready to run, or needs more lines first: it ends
+    /// inside quotes, after a line-joining backslash, after `|`, `&&` or `||`,
+    /// inside a compound command, or before a here-document reached its
+    /// delimiter. Input with a syntax error
+    /// counts as complete, so that the error gets reported.
+    pub fn is_complete(input: &str) -> bool {
+        let mut parser = Parser::new(input);
+        if let Err(e) = parser.tokenize() {
+            return !e.starts_with("unexpected EOF") && e != "Trailing backslash";
+        }
+        if parser.unterminated_heredoc.is_some() {
+            return false;
+        }
+        let mut tokens = parser.tokens.into_iter().peekable();
+        !matches!(parse_list(&mut tokens), Err(e) if e == unexpected(None))
+    }
+
+    /// Finds the `)` closing a `$(` whose body starts `text`, returning its
+    /// byte offset. It is the first `)` that the text before it parses up to
+    /// as a whole list of commands, so that the `)` of a `case` pattern does
+    /// not end the body, or for `$((...))` the first that balances the
+    /// parentheses of an arithmetic expression.
+    pub fn substitution_end(text: &str) -> Option<usize> {
+        let is_list = |body: &str| {
+            let mut parser = Parser::new(body);
+            if parser.tokenize().is_err() || parser.unterminated_heredoc.is_some() {
+                return false;
+            }
+            let mut tokens = parser.tokens.into_iter().peekable();
+            parse_list(&mut tokens).is_ok() && tokens.next().is_none()
+        };
+        let balanced = |body: &str| {
+            let mut depth = 0;
+            for ch in body.chars() {
+                match ch {
+                    '(' => depth += 1,
+                    ')' if depth == 0 => return false,
+                    ')' => depth -= 1,
+                    _ => {}
+                }
+            }
+            depth == 0
+        };
+        text.match_indices(')').map(|(end, _)| end).find(|&end| {
+            let body = &text[..end];
+            (body.starts_with('(') && balanced(body)) || is_list(body)
+        })
+    }
+
+    fn tokenize(&mut self) -> Result<(), String> {
+        while let Some(ch) = self.chars.next() {
+            self.state = match self.state {
+                ParseState::Normal => self.handle_normal(ch)?,
+                ParseState::InSingleQuote => self.handle_in_single_quote(ch),
+                ParseState::InDoubleQuote => self.handle_in_double_quote(ch)?,
+            }
+        }
+
+        match self.state {
+            ParseState::Normal => {}
+            ParseState::InSingleQuote => {
+                return Err("unexpected EOF while looking for matching `''".into())
+            }
+            ParseState::InDoubleQuote => {
+                return Err("unexpected EOF while looking for matching `\"'".into())
+            }
+        }
+        self.push_word();
+        self.read_heredocs();
+        Ok(())
+    }
+
+    /// Ends the current word. Words keep their quotes and escapes so that
+    /// expansion can tell quoted text apart when the command is run.
+    fn push_word(&mut self) {
+        if !self.current.is_empty() {
+            self.tokens
+                .push(Token::Word(std::mem::take(&mut self.current)));
+        }
+    }
+
+    /// Copies a `$(...)` command substitution, starting at its opening
+    /// parenthesis, into the current word.
+    fn read_substitution(&mut self) -> Result<(), String> {
+        self.current.extend(self.chars.next());
+        let rest: String = self.chars.clone().collect();
+        let end = Parser::substitution_end(&rest)
+            .ok_or("unexpected EOF while looking for matching `)'")?;
+        self.current.push_str(&rest[..=end]);
+        for _ in rest[..=end].chars() {
+            self.chars.next();
+        }
+        Ok(())
+    }
+
+    /// Copies a `${...}` expansion, starting at its opening
+    /// bracket, into the current word so that quotes, spaces and operators
+    /// inside it do not end the word.
+    fn read_enclosed(&mut self, open: char, close: char) -> Result<(), String> {
+        let mut depth = 0;
+        let mut state = ParseState::Normal;
+        while let Some(ch) = self.chars.next() {
+            self.current.push(ch);
+            state = match (state, ch) {
+                (ParseState::Normal, c) if c == open => {
+                    depth += 1;
+                    ParseState::Normal
+                }
+                (ParseState::Normal, c) if c == close => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                    ParseState::Normal
+                }
+                (ParseState::Normal, '\\') | (ParseState::InDoubleQuote, '\\') => {
+                    if let Some(escaped) = self.chars.next() {
+                        self.current.push(escaped);
+                    }
+                    state
+                }
+                (ParseState::Normal, '\'') => ParseState::InSingleQuote,
+                (ParseState::Normal, '"') => ParseState::InDoubleQuote,
+                (ParseState::InSingleQuote, '\'') | (ParseState::InDoubleQuote, '"') => {
+                    ParseState::Normal
+                }
+                (state, _) => state,
+            };
+        }
+        Err(format!(
+            "unexpected EOF while looking for matching `{}'",
+            close
+        ))
+    }
+
+    /// Copies the rest of a `` `...` `` command substitution into the current
+    /// word.
+    fn read_backquoted(&mut self) -> Result<(), String> {
+        while let Some(ch) = self.chars.next() {
+            self.current.push(ch);
+            match ch {
+                '`' => return Ok(()),
+                '\\' => {
+                    if let Some(escaped) = self.chars.next() {
+                        self.current.push(escaped);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Err("unexpected EOF while looking for matching ``'".into())
+    }
+
+    /// Copies the expansion introduced by a `$` or backquote that was just
+    /// pushed, if it is one that can contain spaces.
+    fn read_expansion(&mut self, ch: char) -> Result<(), String> {
+        match (ch, self.chars.peek()) {
+            ('`', _) => self.read_backquoted(),
+            ('$', Some('{')) => self.read_enclosed('{', '}'),
+            ('$', Some('(')) => self.read_substitution(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Reads the text up to the `))` closing an arithmetic expression whose
+    /// `((` was just read.
+    fn read_arithmetic(&mut self) -> Result<String, String> {
+        let mut expression = String::new();
+        let mut depth = 0;
+        while let Some(ch) = self.chars.next() {
+            match ch {
+                '(' => depth += 1,
+                ')' if depth == 0 && self.chars.next_if_eq(&')').is_some() => {
+                    return Ok(expression);
+                }
+                ')' => depth -= 1,
+                _ => {}
             }
+            expression.push(ch);
         }
+        Err("unexpected EOF while looking for matching `))'".into())
+    }
 
-        if !parser.current.is_empty() {
-            parser.args.push(parser.current.clone());
+    /// Reads the raw delimiter word after `<<` or `<<-`.
+    fn read_delimiter(&mut self) -> Result<String, String> {
+        while self.chars.next_if(|&c| c == ' ' || c == '\t').is_some() {}
+
+        let mut delimiter = String::new();
+        let mut quote = None;
+        while let Some(&ch) = self.chars.peek() {
+            match quote {
+                Some(q) if ch == q => quote = None,
+                Some(_) => {}
+                None if ch == '\'' || ch == '"' => quote = Some(ch),
+                None if ch == '\\' => {
+                    delimiter.push(ch);
+                    self.chars.next();
+                }
+                None if ch.is_whitespace() || "|&;<>()".contains(ch) => break,
+                None => {}
+            }
+            if let Some(ch) = self.chars.next() {
+                delimiter.push(ch);
+            }
         }
 
-        let res = parser.handle_redirections();
-        Ok(res)
+        if delimiter.is_empty() {
+            let next = match self.chars.peek() {
+                Some(ch) if *ch != '\n' => ch.to_string(),
+                _ => "newline".into(),
+            };
+            return Err(format!("syntax error near unexpected token `{}'", next));
+        }
+        Ok(delimiter)
+    }
+
+    /// Reads the bodies of the here-documents started on the line that just
+    /// ended, in the order they appeared.
+    fn read_heredocs(&mut self) {
+        for index in std::mem::take(&mut self.pending_heredocs) {
+            let Token::HereDoc {
+                strip_tabs,
+                delimiter,
+                ..
+            } = &self.tokens[index]
+            else {
+                continue;
+            };
+            let (strip_tabs, delimiter) = (*strip_tabs, unquote(delimiter));
+
+            let mut body = String::new();
+            loop {
+                let Some(line) = self.read_line() else {
+                    self.unterminated_heredoc = Some(delimiter);
+                    break;
+                };
+                let line = if strip_tabs {
+                    line.trim_start_matches('\t')
+                } else {
+                    &line
+                };
+                if line == delimiter {
+                    break;
+                }
+                body.push_str(line);
+                body.push('\n');
+            }
+
+            if let Token::HereDoc { body: slot, .. } = &mut self.tokens[index] {
+                *slot = body;
+            }
+        }
+    }
+
+    fn read_line(&mut self) -> Option<String> {
+        self.chars.peek()?;
+        Some(self.chars.by_ref().take_while(|&ch| ch != '\n').collect())
+    }
+
+    fn push_operator(&mut self, token: Token) {
+        self.push_word();
+        self.tokens.push(token);
     }
 
     fn handle_normal(&mut self, ch: char) -> Result<ParseState, String> {
         match ch {
             '\\' => {
-                // Escape the next character if present.
-                if let Some(escaped) = self.chars.next() {
-                    self.current.push(escaped);
+                // A backslash before a newline joins the two lines; any other
+                // escape stays with the escaped character.
+                match self.chars.next() {
+                    Some('\n') => {}
+                    Some(escaped) => {
+                        self.current.push(ch);
+                        self.current.push(escaped);
+                    }
+                    None => return Err("Trailing backslash".into()),
+                }
+                Ok(ParseState::Normal)
+            }
+            '#' if self.current.is_empty() => {
+                // A comment runs up to, but not including, the newline.
+                while self.chars.next_if(|&c| c != '\n').is_some() {}
+                Ok(ParseState::Normal)
+            }
+            '\'' => {
+                self.current.push(ch);
+                Ok(ParseState::InSingleQuote)
+            }
+            '"' => {
+                self.current.push(ch);
+                Ok(ParseState::InDoubleQuote)
+            }
+            '$' | '`' => {
+                self.current.push(ch);
+                self.read_expansion(ch)?;
+                Ok(ParseState::Normal)
+            }
+            '|' => {
+                if self.chars.next_if_eq(&'|').is_some() {
+                    self.push_operator(Token::Or);
                 } else {
-                    return Err("Trailing backslash".into());
+                    self.push_operator(Token::Pipe);
                 }
                 Ok(ParseState::Normal)
             }
-            '\'' => Ok(ParseState::InSingleQuote),
-            '"' => Ok(ParseState::InDoubleQuote),
-            c if c.is_whitespace() => {
-                if !self.current.is_empty() {
-                    self.args.push(std::mem::take(&mut self.current));
+            '&' if self.chars.next_if_eq(&'&').is_some() => {
+                self.push_operator(Token::And);
+                Ok(ParseState::Normal)
+            }
+            '&' if self.chars.next_if_eq(&'>').is_some() => {
+                let append = self.chars.next_if_eq(&'>').is_some();
+                self.push_operator(Token::RedirectAll(append));
+                Ok(ParseState::Normal)
+            }
+            '&' => {
+                self.push_operator(Token::Amp);
+                Ok(ParseState::Normal)
+            }
+            ';' => {
+                if self.chars.next_if_eq(&';').is_some() {
+                    self.push_operator(Token::DoubleSemi);
+                } else {
+                    self.push_operator(Token::Semi);
                 }
                 Ok(ParseState::Normal)
             }
+            // `for ((` starts an arithmetic `for`, read as a whole.
+            '(' if self.current.is_empty()
+                && self.chars.peek() == Some(&'(')
+                && matches!(self.tokens.last(), Some(Token::Word(w)) if w == "for") =>
+            {
+                self.chars.next();
+                let expression = self.read_arithmetic()?;
+                self.push_operator(Token::Arithmetic(expression));
+                Ok(ParseState::Normal)
+            }
+            '(' => {
+                self.push_operator(Token::LParen);
+                Ok(ParseState::Normal)
+            }
+            ')' => {
+                self.push_operator(Token::RParen);
+                Ok(ParseState::Normal)
+            }
+            '\n' => {
+                self.push_operator(Token::Newline);
+                self.read_heredocs();
+                Ok(ParseState::Normal)
+            }
+            '<' | '>' => {
+                // An unquoted number right before the operator names the
+                // descriptor.
+                let fd = match self.current.parse() {
+                    Ok(fd) if self.current.chars().all(|c| c.is_ascii_digit()) => {
+                        self.current.clear();
+                        Some(fd)
+                    }
+                    _ => None,
+                };
+                if ch == '<' && self.chars.next_if_eq(&'<').is_some() {
+                    if self.chars.next_if_eq(&'<').is_some() {
+                        self.push_operator(Token::Redirect(fd, RedirectOp::HereString));
+                        return Ok(ParseState::Normal);
+                    }
+                    let strip_tabs = self.chars.next_if_eq(&'-').is_some();
+                    let delimiter = self.read_delimiter()?;
+                    self.push_operator(Token::HereDoc {
+                        fd,
+                        strip_tabs,
+                        delimiter,
+                        body: String::new(),
+                    });
+                    self.pending_heredocs.push(self.tokens.len() - 1);
+                    return Ok(ParseState::Normal);
+                }
+
+                let op = if ch == '<' {
+                    match self.chars.next_if(|c| matches!(c, '>' | '&')) {
+                        Some('>') => RedirectOp::ReadWrite,
+                        Some(_) => RedirectOp::DuplicateInput,
+                        None => RedirectOp::Input,
+                    }
+                } else {
+                    match self.chars.next_if(|c| matches!(c, '>' | '&' | '|')) {
+                        Some('>') => RedirectOp::Append,
+                        Some('&') => RedirectOp::DuplicateOutput,
+                        Some(_) => RedirectOp::Clobber,
+                        None => RedirectOp::Output,
+                    }
+                };
+                self.push_operator(Token::Redirect(fd, op));
+                Ok(ParseState::Normal)
+            }
+            c if c.is_whitespace() => {
+                self.push_word();
+                Ok(ParseState::Normal)
+            }
             _ => {
                 self.current.push(ch);
                 Ok(ParseState::Normal)
@@ -77,90 +530,444 @@ impl Parser<'_> {
     }
 
     fn handle_in_single_quote(&mut self, ch: char) -> ParseState {
+        self.current.push(ch);
         if ch == '\'' {
             ParseState::Normal
         } else {
-            self.current.push(ch);
             ParseState::InSingleQuote
         }
     }
 
     fn handle_in_double_quote(&mut self, ch: char) -> Result<ParseState, String> {
+        self.current.push(ch);
         match ch {
             '"' => Ok(ParseState::Normal),
             '\\' => {
-                // Only escape certain characters within double quotes.
-                if let Some(&next_ch) = self.chars.peek() {
-                    match next_ch {
-                        '\\' | '"' | '$' | '\n' => {
-                            self.current.push(self.chars.next().unwrap());
-                        }
-                        _ => {
-                            self.current.push('\\');
-                        }
-                    }
+                // The escaped character can never end the quote.
+                if let Some(escaped) = self.chars.next() {
+                    self.current.push(escaped);
                     Ok(ParseState::InDoubleQuote)
                 } else {
                     Err("Trailing backslash in double quotes".into())
                 }
             }
-            _ => {
-                self.current.push(ch);
+            '$' | '`' => {
+                self.read_expansion(ch)?;
                 Ok(ParseState::InDoubleQuote)
             }
+            _ => Ok(ParseState::InDoubleQuote),
         }
     }
+}
 
-    fn handle_redirections(&self) -> ParseOutput {
-        let mut commands = Vec::new();
-        let mut args = Vec::new();
-        let mut stdout_target = None;
-        let mut stderr_target = None;
+type Tokens = Peekable<std::vec::IntoIter<Token>>;
 
-        let mut iter = self.args.iter();
-        while let Some(val) = iter.next() {
-            match val.as_str() {
-                "|" => {
-                    if !args.is_empty() {
-                        commands.push(std::mem::take(&mut args));
-                    }
+fn unexpected(token: Option<&Token>) -> String {
+    match token {
+        Some(token) => format!("syntax error near unexpected token `{}'", token),
+        None => "syntax error: unexpected end of input".into(),
+    }
+}
+
+fn parse_list(tokens: &mut Tokens) -> Result<List, String> {
+    let mut list = Vec::new();
+    loop {
+        skip_newlines(tokens);
+        if tokens.peek().map_or(true, ends_list) {
+            break;
+        }
+        let mut and_or = parse_and_or(tokens)?;
+        let separator =
+            tokens.next_if(|token| matches!(token, Token::Semi | Token::Newline | Token::Amp));
+        and_or.background = separator == Some(Token::Amp);
+        list.push(and_or);
+        if separator.is_none() {
+            break;
+        }
+    }
+    Ok(List(list))
+}
+
+/// Whether `token`, at the start of a command, closes the compound command
+/// the list is part of.
+fn ends_list(token: &Token) -> bool {
+    const CLOSING: [&str; 8] = ["}", "then", "elif", "else", "fi", "do", "done", "esac"];
+    matches!(token, Token::RParen | Token::DoubleSemi)
+        || CLOSING.iter().any(|word| is_reserved(token, word))
+}
+
+/// Whether `token` opens a compound command.
+fn starts_compound(token: &Token) -> bool {
+    const OPENING: [&str; 6] = ["{", "if", "while", "until", "for", "case"];
+    matches!(token, Token::LParen) || OPENING.iter().any(|word| is_reserved(token, word))
+}
+
+/// Whether `token` is the reserved word `word`. Reserved words are only
+/// recognised where a command name could start, and only unquoted.
+fn is_reserved(token: &Token, word: &str) -> bool {
+    matches!(token, Token::Word(w) if w == word)
+}
+
+/// Consumes the reserved word `word`, failing if something else comes next.
+fn expect_reserved(tokens: &mut Tokens, word: &str) -> Result<(), String> {
+    match tokens.next() {
+        Some(token) if is_reserved(&token, word) => Ok(()),
+        token => Err(unexpected(token.as_ref())),
+    }
+}
+
+/// Parses a list that must contain at least one command, as compound
+/// commands require.
+fn parse_body(tokens: &mut Tokens) -> Result<List, String> {
+    let list = parse_list(tokens)?;
+    if list.0.is_empty() {
+        return Err(unexpected(tokens.peek()));
+    }
+    Ok(list)
+}
+
+/// Skips the line breaks allowed after an operator or between commands.
+fn skip_newlines(tokens: &mut Tokens) {
+    while tokens.next_if_eq(&Token::Newline).is_some() {}
+}
+
+fn parse_and_or(tokens: &mut Tokens) -> Result<AndOr, String> {
+    let first = parse_pipeline(tokens)?;
+    let mut rest = Vec::new();
+    loop {
+        let connector = match tokens.peek() {
+            Some(Token::And) => Connector::And,
+            Some(Token::Or) => Connector::Or,
+            _ => break,
+        };
+        tokens.next();
+        skip_newlines(tokens);
+        rest.push((connector, parse_pipeline(tokens)?));
+    }
+    Ok(AndOr {
+        first,
+        rest,
+        background: false,
+    })
+}
+
+fn parse_pipeline(tokens: &mut Tokens) -> Result<Pipeline, String> {
+    let mut commands = vec![parse_command(tokens)?];
+    while tokens.next_if_eq(&Token::Pipe).is_some() {
+        skip_newlines(tokens);
+        commands.push(parse_command(tokens)?);
+    }
+    Ok(Pipeline { commands })
+}
+
+fn parse_command(tokens: &mut Tokens) -> Result<Command, String> {
+    match tokens.peek() {
+        Some(token) if starts_compound(token) => parse_compound(tokens),
+        Some(token) if is_reserved(token, "function") => {
+            tokens.next();
+            let Some(Token::Word(name)) = tokens.next() else {
+                return Err(unexpected(tokens.peek()));
+            };
+            if tokens.next_if_eq(&Token::LParen).is_some() {
+                expect_token(tokens, Token::RParen)?;
+            }
+            parse_function(name, tokens)
+        }
+        _ => parse_simple_command(tokens),
+    }
+}
+
+/// Parses the body of a function whose name and `()` were just read.
+fn parse_function(name: String, tokens: &mut Tokens) -> Result<Command, String> {
+    if !expand::is_name(&name) {
+        return Err(format!("`{}': not a valid identifier", name));
+    }
+    skip_newlines(tokens);
+    match tokens.peek() {
+        Some(token) if starts_compound(token) => {}
+        token => return Err(unexpected(token)),
+    }
+    Ok(Command::Function(name, Rc::new(parse_compound(tokens)?)))
+}
+
+fn expect_token(tokens: &mut Tokens, expected: Token) -> Result<(), String> {
+    match tokens.next() {
+        Some(token) if token == expected => Ok(()),
+        token => Err(unexpected(token.as_ref())),
+    }
+}
+
+/// Parses a compound command and the redirections after it.
+fn parse_compound(tokens: &mut Tokens) -> Result<Command, String> {
+    let compound = match tokens.next() {
+        Some(Token::LParen) => {
+            let list = parse_body(tokens)?;
+            expect_token(tokens, Token::RParen)?;
+            CompoundCommand::Subshell(list)
+        }
+        Some(Token::Word(word)) => match word.as_str() {
+            "{" => {
+                let list = parse_body(tokens)?;
+                expect_reserved(tokens, "}")?;
+                CompoundCommand::Group(list)
+            }
+            "if" => parse_if(tokens)?,
+            "while" | "until" => {
+                let condition = parse_body(tokens)?;
+                CompoundCommand::Loop {
+                    until: word == "until",
+                    condition,
+                    body: parse_do_group(tokens)?,
                 }
-                ">" | "1>" | ">>" | "1>>" => {
-                    let mut append = false;
-                    if val.contains(">>") {
-                        append = true;
-                    }
-                    if let Some(file) = iter.next() {
-                        stdout_target = Some((file.clone(), append));
-                    } else {
-                        eprintln!("Error: No file specified for redirection");
-                        break;
+            }
+            "for" => parse_for(tokens)?,
+            "case" => parse_case(tokens)?,
+            _ => unreachable!("not the start of a compound command"),
+        },
+        _ => unreachable!("not the start of a compound command"),
+    };
+
+    let mut redirects = Vec::new();
+    while parse_redirect(tokens, &mut redirects)? {}
+    Ok(Command::Compound(Rc::new(compound), redirects))
+}
+
+fn parse_if(tokens: &mut Tokens) -> Result<CompoundCommand, String> {
+    let mut branches = Vec::new();
+    let mut otherwise = None;
+    loop {
+        let condition = parse_body(tokens)?;
+        expect_reserved(tokens, "then")?;
+        branches.push((condition, parse_body(tokens)?));
+        match tokens.next() {
+            Some(token) if is_reserved(&token, "elif") => continue,
+            Some(token) if is_reserved(&token, "else") => {
+                otherwise = Some(parse_body(tokens)?);
+                expect_reserved(tokens, "fi")?;
+                break;
+            }
+            Some(token) if is_reserved(&token, "fi") => break,
+            token => return Err(unexpected(token.as_ref())),
+        }
+    }
+    Ok(CompoundCommand::If {
+        branches,
+        otherwise,
+    })
+}
+
+/// Parses `do list; done`.
+fn parse_do_group(tokens: &mut Tokens) -> Result<List, String> {
+    skip_newlines(tokens);
+    expect_reserved(tokens, "do")?;
+    let body = parse_body(tokens)?;
+    expect_reserved(tokens, "done")?;
+    Ok(body)
+}
+
+fn parse_for(tokens: &mut Tokens) -> Result<CompoundCommand, String> {
+    let name = match tokens.next() {
+        Some(Token::Arithmetic(expression)) => {
+            let parts: Vec<&str> = expression.split(';').collect();
+            let [init, condition, step] = parts[..] else {
+                return Err("syntax error: arithmetic expression required".into());
+            };
+            tokens.next_if_eq(&Token::Semi);
+            return Ok(CompoundCommand::ArithmeticFor {
+                init: init.trim().to_string(),
+                condition: condition.trim().to_string(),
+                step: step.trim().to_string(),
+                body: parse_do_group(tokens)?,
+            });
+        }
+        Some(Token::Word(name)) if expand::is_name(&name) => name,
+        Some(Token::Word(name)) => return Err(format!("`{}': not a valid identifier", name)),
+        token => return Err(unexpected(token.as_ref())),
+    };
+
+    skip_newlines(tokens);
+    let words = if tokens.next_if(|token| is_reserved(token, "in")).is_some() {
+        let mut words = Vec::new();
+        while let Some(Token::Word(word)) = tokens.next_if(|t| matches!(t, Token::Word(_))) {
+            words.push(word);
+        }
+        match tokens.next() {
+            Some(Token::Semi | Token::Newline) => {}
+            token => return Err(unexpected(token.as_ref())),
+        }
+        Some(words)
+    } else {
+        tokens.next_if_eq(&Token::Semi);
+        None
+    };
+
+    Ok(CompoundCommand::For {
+        name,
+        words,
+        body: parse_do_group(tokens)?,
+    })
+}
+
+fn parse_case(tokens: &mut Tokens) -> Result<CompoundCommand, String> {
+    let Some(Token::Word(word)) = tokens.next() else {
+        return Err(unexpected(tokens.peek()));
+    };
+    skip_newlines(tokens);
+    expect_reserved(tokens, "in")?;
+
+    let mut items = Vec::new();
+    loop {
+        skip_newlines(tokens);
+        if tokens.next_if(|token| is_reserved(token, "esac")).is_some() {
+            break;
+        }
+
+        tokens.next_if_eq(&Token::LParen);
+        let mut patterns = Vec::new();
+        loop {
+            match tokens.next() {
+                Some(Token::Word(pattern)) => patterns.push(pattern),
+                token => return Err(unexpected(token.as_ref())),
+            }
+            if tokens.next_if_eq(&Token::Pipe).is_none() {
+                break;
+            }
+        }
+        expect_token(tokens, Token::RParen)?;
+
+        let body = parse_list(tokens)?;
+        items.push(CaseItem { patterns, body });
+        if tokens.next_if_eq(&Token::DoubleSemi).is_none() {
+            skip_newlines(tokens);
+            expect_reserved(tokens, "esac")?;
+            break;
+        }
+    }
+    Ok(CompoundCommand::Case { word, items })
+}
+
+fn parse_simple_command(tokens: &mut Tokens) -> Result<Command, String> {
+    let mut command = SimpleCommand::default();
+    loop {
+        match tokens.peek() {
+            Some(Token::Word(_)) => {
+                if let Some(Token::Word(word)) = tokens.next() {
+                    match assignment(&word) {
+                        Some(assignment) if command.words.is_empty() => {
+                            command.assignments.push(assignment);
+                        }
+                        _ => command.words.push(word),
                     }
                 }
-                "2>" | "2>>" => {
-                    let mut append = false;
-                    if val.contains(">>") {
-                        append = true;
-                    }
-                    if let Some(file) = iter.next() {
-                        stderr_target = Some((file.clone(), append));
-                    } else {
-                        eprintln!("Error: No file specified for redirection");
-                        break;
-                    }
+            }
+            // `name()` starts a function definition.
+            Some(Token::LParen)
+                if command.words.len() == 1
+                    && command.assignments.is_empty()
+                    && command.redirects.is_empty() =>
+            {
+                tokens.next();
+                expect_token(tokens, Token::RParen)?;
+                return parse_function(command.words.remove(0), tokens);
+            }
+            _ => {
+                if !parse_redirect(tokens, &mut command.redirects)? {
+                    break;
                 }
-                _ => args.push(val.clone()),
             }
         }
+    }
 
-        if !args.is_empty() {
-            commands.push(std::mem::take(&mut args));
+    if command.words.is_empty() && command.assignments.is_empty() && command.redirects.is_empty() {
+        return Err(unexpected(tokens.peek()));
+    }
+    Ok(Command::Simple(command))
+}
+
+/// Parses a redirection if one comes next, returning whether it did.
+fn parse_redirect(tokens: &mut Tokens, redirects: &mut Vec<Redirect>) -> Result<bool, String> {
+    match tokens.peek() {
+        Some(Token::Redirect(..) | Token::RedirectAll(_)) => {
+            let operator = tokens.next();
+            let Some(Token::Word(target)) = tokens.next() else {
+                return Err("Error: No file specified for redirection".into());
+            };
+            match operator {
+                Some(Token::Redirect(fd, op)) => redirects.push(Redirect {
+                    fd: fd.unwrap_or(op.default_fd()),
+                    op,
+                    target,
+                    delimiter: None,
+                }),
+                Some(Token::RedirectAll(append)) => {
+                    let op = if append {
+                        RedirectOp::Append
+                    } else {
+                        RedirectOp::Output
+                    };
+                    redirects.push(Redirect {
+                        fd: 1,
+                        op,
+                        target,
+                        delimiter: None,
+                    });
+                    redirects.push(Redirect {
+                        fd: 2,
+                        op: RedirectOp::DuplicateOutput,
+                        target: "1".into(),
+                        delimiter: None,
+                    });
+                }
+                _ => unreachable!(),
+            }
         }
+        Some(Token::HereDoc { .. }) => {
+            let Some(Token::HereDoc {
+                fd,
+                delimiter,
+                body,
+                ..
+            }) = tokens.next()
+            else {
+                unreachable!()
+            };
+            // Quoting any part of the delimiter turns off expansion in the
+            // body.
+            let op = if delimiter.contains(['\'', '"', '\\']) {
+                RedirectOp::LiteralHereDoc
+            } else {
+                RedirectOp::HereDoc
+            };
+            redirects.push(Redirect {
+                fd: fd.unwrap_or(0),
+                op,
+                target: body,
+                delimiter: Some(delimiter),
+            });
+        }
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Splits a raw `NAME=value` word into its name and still unexpanded value.
+fn assignment(word: &str) -> Option<(String, String)> {
+    let (name, value) = word.split_once('=')?;
+    expand::is_name(name).then(|| (name.to_string(), value.to_string()))
+}
 
-        ParseOutput {
-            commands,
-            out_target: stdout_target,
-            err_target: stderr_target,
+/// Removes the quotes and backslashes from a here-document delimiter.
+fn unquote(word: &str) -> String {
+    let mut unquoted = String::new();
+    let mut chars = word.chars();
+    let mut quote = None;
+    while let Some(ch) = chars.next() {
+        match (quote, ch) {
+            (None, '\'' | '"') => quote = Some(ch),
+            (Some(q), _) if ch == q => quote = None,
+            (None, '\\') => unquoted.extend(chars.next()),
+            _ => unquoted.push(ch),
         }
     }
+    unquoted
 }
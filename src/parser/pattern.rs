@@ -0,0 +1,171 @@
+/// A shell pattern as used by `${var#pattern}` and friends: `*`, `?` and
+/// bracket expressions, with quoted characters only matching themselves.
+#[derive(Debug)]
+pub struct Pattern(Vec<Token>);
+
+#[derive(Debug)]
+enum Token {
+    Char(char),
+    /// `?`
+    Any,
+    /// `*`
+    Star,
+    /// `[...]`, matching any of the inclusive ranges unless negated.
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Token {
+    fn matches(&self, ch: char) -> bool {
+        match self {
+            Token::Char(c) => *c == ch,
+            Token::Any => true,
+            Token::Star => false,
+            Token::Class { negated, ranges } => {
+                ranges.iter().any(|&(lo, hi)| lo <= ch && ch <= hi) != *negated
+            }
+        }
+    }
+}
+
+impl Pattern {
+    /// Compiles a pattern from characters tagged with whether they were
+    /// quoted.
+    pub fn new(chars: &[(char, bool)]) -> Self {
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let token = match chars[i] {
+                ('*', false) => Token::Star,
+                ('?', false) => Token::Any,
+                ('[', false) => match parse_class(&chars[i + 1..]) {
+                    Some((class, len)) => {
+                        i += len;
+                        class
+                    }
+                    None => Token::Char('['),
+                },
+                (ch, _) => Token::Char(ch),
+            };
+            tokens.push(token);
+            i += 1;
+        }
+        Pattern(tokens)
+    }
+
+    /// Whether the pattern matches all of `text`.
+    pub fn matches(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        self.matches_chars(&text)
+    }
+
+    fn matches_chars(&self, text: &[char]) -> bool {
+        let tokens = &self.0;
+        let (mut p, mut t) = (0, 0);
+        // Where to resume after the last `*` if the rest fails to match.
+        let mut backtrack = None;
+
+        while t < text.len() {
+            match tokens.get(p) {
+                Some(Token::Star) => {
+                    backtrack = Some((p, t));
+                    p += 1;
+                }
+                Some(token) if token.matches(text[t]) => {
+                    p += 1;
+                    t += 1;
+                }
+                _ => match backtrack {
+                    Some((star, from)) => {
+                        p = star + 1;
+                        t = from + 1;
+                        backtrack = Some((star, from + 1));
+                    }
+                    None => return false,
+                },
+            }
+        }
+
+        tokens[p..].iter().all(|token| matches!(token, Token::Star))
+    }
+
+    /// Length in chars of the shortest or longest prefix of `text` that the
+    /// pattern matches.
+    pub fn match_prefix(&self, text: &[char], longest: bool) -> Option<usize> {
+        let mut lengths: Box<dyn Iterator<Item = usize>> = if longest {
+            Box::new((0..=text.len()).rev())
+        } else {
+            Box::new(0..=text.len())
+        };
+        lengths.find(|&len| self.matches_chars(&text[..len]))
+    }
+
+    /// Length in chars of the shortest or longest suffix of `text` that the
+    /// pattern matches.
+    pub fn match_suffix(&self, text: &[char], longest: bool) -> Option<usize> {
+        let mut lengths: Box<dyn Iterator<Item = usize>> = if longest {
+            Box::new((0..=text.len()).rev())
+        } else {
+            Box::new(0..=text.len())
+        };
+        lengths.find(|&len| self.matches_chars(&text[text.len() - len..]))
+    }
+}
+
+/// Parses the inside of a bracket expression, returning the class and the
+/// number of characters consumed including the closing `]`.
+fn parse_class(chars: &[(char, bool)]) -> Option<(Token, usize)> {
+    let mut i = 0;
+    let negated = matches!(chars.first(), Some(('!' | '^', false)));
+    if negated {
+        i += 1;
+    }
+
+    let mut ranges = Vec::new();
+    let start = i;
+    loop {
+        let &(ch, quoted) = chars.get(i)?;
+        if ch == ']' && !quoted && i > start {
+            return Some((Token::Class { negated, ranges }, i + 1));
+        }
+
+        if ch == '[' && !quoted && matches!(chars.get(i + 1), Some((':', false))) {
+            let rest: String = chars[i + 2..].iter().map(|(c, _)| *c).collect();
+            if let Some(end) = rest.find(":]") {
+                if let Some(class) = named_class(&rest[..end]) {
+                    ranges.extend_from_slice(class);
+                    i += 2 + rest[..end].chars().count() + 2;
+                    continue;
+                }
+            }
+        }
+
+        match (chars.get(i + 1), chars.get(i + 2)) {
+            (Some(('-', false)), Some(&(hi, _))) if hi != ']' => {
+                ranges.push((ch, hi));
+                i += 3;
+            }
+            _ => {
+                ranges.push((ch, ch));
+                i += 1;
+            }
+        }
+    }
+}
+
+fn named_class(name: &str) -> Option<&'static [(char, char)]> {
+    Some(match name {
+        "alpha" => &[('a', 'z'), ('A', 'Z')],
+        "digit" => &[('0', '9')],
+        "alnum" => &[('a', 'z'), ('A', 'Z'), ('0', '9')],
+        "upper" => &[('A', 'Z')],
+        "lower" => &[('a', 'z')],
+        "space" => &[(' ', ' '), ('\t', '\r')],
+        "blank" => &[(' ', ' '), ('\t', '\t')],
+        "xdigit" => &[('0', '9'), ('a', 'f'), ('A', 'F')],
+        "punct" => &[('!', '/'), (':', '@'), ('[', '`'), ('{', '~')],
+        _ => return None,
+    })
+}
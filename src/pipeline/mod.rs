@@ -0,0 +1,210 @@
+pub mod redirect;
+
+use std::io::Write;
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{ExitStatus, Stdio};
+use std::rc::Rc;
+
+use crate::command::{error_text, exit_code, Command, Prefixed, Runnable};
+use crate::job::{self, Job, Process};
+use crate::parser::ast::CompoundCommand;
+use crate::shell::Shell;
+use redirect::Redirections;
+
+/// A pipeline stage after expansion.
+pub struct Stage {
+    /// `NAME=value` assignments for this stage's environment only.
+    pub env: Vec<(String, String)>,
+    pub args: Vec<String>,
+    /// Applied on top of the pipes connecting the stage.
+    pub redirections: Redirections,
+    /// A compound command to run in a forked shell instead of `args`.
+    pub compound: Option<Rc<CompoundCommand>>,
+}
+
+/// Runs a pipeline in the foreground and returns the exit status of every
+/// stage once all of them have finished, or once the job is stopped.
+pub fn run(stages: Vec<Stage>, command: String, shell: &mut Shell) -> std::io::Result<Vec<i32>> {
+    let job = spawn(stages, command, true, shell)?;
+    shell.wait_foreground(job)
+}
+
+/// Starts every stage of a pipeline at once, connected by OS pipes, without
+/// waiting for them.
+///
+/// The first stage reads the shell's stdin, the last writes to its stdout and
+/// all of them share its stderr, unless redirected. With job control the
+/// stages share a new process group, which gets the terminal if `foreground`.
+pub fn spawn(
+    stages: Vec<Stage>,
+    command: String,
+    foreground: bool,
+    shell: &mut Shell,
+) -> std::io::Result<Job> {
+    let mut processes = Vec::new();
+    let mut pgid = 0;
+    let mut stdin: Option<OwnedFd> = None;
+    if !foreground && !shell.jobs().enabled() {
+        // Without job control, background jobs must not compete for input.
+        stdin = Some(std::fs::File::open("/dev/null")?.into());
+    }
+
+    let count = stages.len();
+    for (i, stage) in stages.into_iter().enumerate() {
+        let Stage {
+            env,
+            args,
+            redirections,
+            compound,
+        } = stage;
+        let (out, next_stdin) = if i == count - 1 {
+            (None, None)
+        } else {
+            let (reader, writer) = os_pipe::pipe()?;
+            (Some(OwnedFd::from(writer)), Some(OwnedFd::from(reader)))
+        };
+
+        let group = shell.jobs().group(pgid, foreground);
+        if let Some(compound) = compound {
+            let spawned = fork(
+                [stdin.take(), out, None],
+                next_stdin.as_ref().map(AsRawFd::as_raw_fd),
+                || {
+                    let _ = job::prepare_child(group);
+                    shell.jobs_mut().disable();
+                    if let Err(e) = redirections.apply() {
+                        shell.report(e);
+                        return 1;
+                    }
+                    match shell.run_compound(&compound) {
+                        Ok(()) => shell.last_status(),
+                        Err(_) => 1,
+                    }
+                },
+            )?;
+            pgid = shell.jobs().join(spawned, group);
+            processes.push(Process::started(spawned));
+            stdin = next_stdin;
+            continue;
+        }
+
+        let Some(name) = args.first().cloned() else {
+            // Every word expanded to nothing; the stage does nothing.
+            processes.push(Process::finished(0));
+            stdin = next_stdin;
+            continue;
+        };
+
+        let spawned = match Command::lookup(&name, shell).with_env(env) {
+            Command::Binary(binary) => {
+                shell.hit(&name);
+                binary
+                    .spawn(
+                        &args,
+                        to_stdio(stdin.take()),
+                        to_stdio(out),
+                        Stdio::inherit(),
+                        &redirections,
+                        group,
+                    )
+                    .map(|child| child.id() as libc::pid_t)
+            }
+            cmd => fork(
+                [stdin.take(), out, None],
+                next_stdin.as_ref().map(AsRawFd::as_raw_fd),
+                || {
+                    let _ = job::prepare_child(group);
+                    shell.jobs_mut().disable();
+                    if let Err(e) = redirections.apply() {
+                        shell.report(e);
+                        return 1;
+                    }
+                    let mut out_writer: Box<dyn Write> = Box::new(std::io::stdout());
+                    let mut err_writer: Box<dyn Write> =
+                        Box::new(Prefixed::new(std::io::stderr(), shell.error_prefix()));
+                    match cmd.run(args, &mut out_writer, &mut err_writer, shell) {
+                        Ok(status) => status,
+                        Err(e) => {
+                            shell.report(format_args!("{}: write error: {}", name, error_text(&e)));
+                            1
+                        }
+                    }
+                },
+            ),
+        };
+
+        match spawned {
+            Ok(pid) => {
+                pgid = shell.jobs().join(pid, group);
+                processes.push(Process::started(pid));
+            }
+            Err(e) => {
+                shell.report(format_args!("{}: {}", name, error_text(&e)));
+                processes.push(Process::finished(
+                    if e.kind() == std::io::ErrorKind::NotFound {
+                        127
+                    } else {
+                        126
+                    },
+                ));
+            }
+        }
+
+        stdin = next_stdin;
+    }
+
+    Ok(Job::new(pgid, processes, command))
+}
+
+fn to_stdio(fd: Option<OwnedFd>) -> Stdio {
+    fd.map_or_else(Stdio::inherit, Stdio::from)
+}
+
+/// Forks the shell and calls `run` in the child with `fds` installed as its
+/// stdin, stdout and stderr, exiting with the status it returns. `close` is a
+/// pipe end owned by a later stage that the child must not keep open.
+pub fn fork(
+    fds: [Option<OwnedFd>; 3],
+    close: Option<RawFd>,
+    run: impl FnOnce() -> i32,
+) -> std::io::Result<libc::pid_t> {
+    std::io::stdout().flush()?;
+    std::io::stderr().flush()?;
+
+    match unsafe { libc::fork() } {
+        -1 => Err(std::io::Error::last_os_error()),
+        0 => {
+            if let Some(fd) = close {
+                unsafe { libc::close(fd) };
+            }
+            for (target, fd) in fds.iter().enumerate() {
+                if let Some(fd) = fd {
+                    unsafe { libc::dup2(fd.as_raw_fd(), target as RawFd) };
+                }
+            }
+            drop(fds);
+
+            let code = run();
+            let _ = std::io::stdout().flush();
+            let _ = std::io::stderr().flush();
+
+            unsafe { libc::_exit(code) }
+        }
+        pid => Ok(pid),
+    }
+}
+
+/// Waits for a forked child and returns its exit status.
+pub fn wait_pid(pid: libc::pid_t) -> std::io::Result<i32> {
+    let mut status = 0;
+    loop {
+        if unsafe { libc::waitpid(pid, &mut status, 0) } != -1 {
+            return Ok(exit_code(ExitStatus::from_raw(status)));
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::io::Write;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::process::CommandExt;
+
+/// Descriptors from here on are kept for the shell's own use, so that files it
+/// holds open never get in the way of the descriptors a user redirects.
+const FIRST_PRIVATE_FD: RawFd = 10;
+
+/// The redirections of one command with their files already opened, ready to
+/// be applied to whichever process ends up running it.
+#[derive(Default)]
+pub struct Redirections {
+    /// Keeps the opened files alive until the command has started.
+    files: Vec<OwnedFd>,
+    /// In order, each redirected descriptor and the one it becomes a copy of,
+    /// or `None` to close it.
+    steps: Vec<(RawFd, Option<RawFd>)>,
+}
+
+impl Redirections {
+    /// Makes `fd` refer to `file`.
+    pub fn open(&mut self, fd: RawFd, file: std::fs::File) -> std::io::Result<()> {
+        let file = private_fd(file.into())?;
+        self.steps.push((fd, Some(file.as_raw_fd())));
+        self.files.push(file);
+        Ok(())
+    }
+
+    /// Makes `fd` a copy of `source`, failing if `source` is not open at that
+    /// point.
+    pub fn duplicate(&mut self, fd: RawFd, source: RawFd) -> std::io::Result<()> {
+        let is_open = match self
+            .steps
+            .iter()
+            .rev()
+            .find(|(target, _)| *target == source)
+        {
+            Some((_, copied)) => copied.is_some(),
+            None => unsafe { libc::fcntl(source, libc::F_GETFD) != -1 },
+        };
+        if !is_open {
+            return Err(std::io::Error::from_raw_os_error(libc::EBADF));
+        }
+        self.steps.push((fd, Some(source)));
+        Ok(())
+    }
+
+    pub fn close(&mut self, fd: RawFd) {
+        self.steps.push((fd, None));
+    }
+
+    /// Applies the redirections to the current process, which is a child
+    /// about to run the command.
+    pub fn apply(&self) -> std::io::Result<()> {
+        apply(&self.steps)
+    }
+
+    /// Arranges for a spawned program to get the redirections once its
+    /// standard streams are set up.
+    pub fn install(&self, command: &mut std::process::Command) {
+        let steps = self.steps.clone();
+        unsafe { command.pre_exec(move || apply(&steps)) };
+    }
+
+    /// Applies the redirections to the shell itself, for a command it runs
+    /// without forking. They are undone when the returned guard is dropped.
+    pub fn apply_in_shell(&self) -> std::io::Result<Saved> {
+        std::io::stdout().flush()?;
+        std::io::stderr().flush()?;
+
+        let mut saved = Saved(Vec::new());
+        for &(fd, _) in &self.steps {
+            if saved.0.iter().any(|(target, _)| *target == fd) {
+                continue;
+            }
+            let copy = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, FIRST_PRIVATE_FD) };
+            let copy = (copy != -1).then(|| unsafe { OwnedFd::from_raw_fd(copy) });
+            saved.0.push((fd, copy));
+        }
+        apply(&self.steps)?;
+        Ok(saved)
+    }
+}
+
+/// The shell's own descriptors as they were before [`Redirections::apply_in_shell`],
+/// put back on drop. A descriptor that was closed is closed again.
+pub struct Saved(Vec<(RawFd, Option<OwnedFd>)>);
+
+impl Drop for Saved {
+    fn drop(&mut self) {
+        let _ = std::io::stdout().flush();
+        let _ = std::io::stderr().flush();
+        for (fd, copy) in self.0.drain(..).rev() {
+            match copy {
+                Some(copy) => unsafe { libc::dup2(copy.as_raw_fd(), fd) },
+                None => unsafe { libc::close(fd) },
+            };
+        }
+    }
+}
+
+fn apply(steps: &[(RawFd, Option<RawFd>)]) -> std::io::Result<()> {
+    for &(fd, source) in steps {
+        let result = match source {
+            // Only the close-on-exec flag stops the descriptor being inherited.
+            Some(source) if source == fd => unsafe { libc::fcntl(fd, libc::F_SETFD, 0) },
+            Some(source) => unsafe { libc::dup2(source, fd) },
+            // Closing a descriptor that is not open is not an error.
+            None => {
+                unsafe { libc::close(fd) };
+                0
+            }
+        };
+        if result == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Moves a descriptor out of the range users redirect.
+fn private_fd(fd: OwnedFd) -> std::io::Result<OwnedFd> {
+    if fd.as_raw_fd() >= FIRST_PRIVATE_FD {
+        return Ok(fd);
+    }
+    match unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_DUPFD_CLOEXEC, FIRST_PRIVATE_FD) } {
+        -1 => Err(std::io::Error::last_os_error()),
+        copy => Ok(unsafe { OwnedFd::from_raw_fd(copy) }),
+    }
+}
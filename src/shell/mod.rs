@@ -0,0 +1,1243 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Read, Write};
+use std::mem::ManuallyDrop;
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::rc::Rc;
+
+use crate::command::{error_text, Command, Prefixed, Runnable};
+use crate::history::{self, History};
+use crate::input::Script;
+use crate::job::{self, Job, Jobs, Process};
+use crate::parser::ast::{
+    self, AndOr, CompoundCommand, Connector, List, Pipeline, Redirect, RedirectOp,
+};
+use crate::parser::{arith, expand, Parser};
+use crate::pipeline::redirect::Redirections;
+use crate::pipeline::{self, Stage};
+
+/// A shell variable. Exported variables are mirrored into the process
+/// environment so that every child inherits them.
+pub struct Variable {
+    /// `None` for a name that was exported before being given a value.
+    pub value: Option<String>,
+    pub exported: bool,
+}
+
+/// Where a program was found in `PATH`, and how many times it has been run
+/// from there.
+pub struct Hashed {
+    pub path: std::path::PathBuf,
+    pub hits: u32,
+}
+
+/// A change of control flow requested by a builtin, which unwinds the
+/// commands being run until something handles it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    /// `return`: leave the function being run.
+    Return,
+    /// `break n`: leave the `n` innermost loops.
+    Break(usize),
+    /// `continue n`: leave `n - 1` loops and start the next iteration of the
+    /// one around them.
+    Continue(usize),
+}
+
+/// State of the running shell that outlives a single input line.
+pub struct Shell {
+    /// Shell variables, seeded from the environment the shell started with.
+    vars: HashMap<String, Variable>,
+    /// `$0`, the name the shell was invoked as.
+    name: String,
+    /// `$1`, `$2`, ...
+    positional: Vec<String>,
+    /// `$$`, which stays the shell's own pid inside forked pipeline stages.
+    pid: u32,
+    last_status: i32,
+    pipestatus: Vec<i32>,
+    /// `$!`, the last process started in the background.
+    last_background: Option<libc::pid_t>,
+    jobs: Jobs,
+    /// Functions by name, each with its body.
+    functions: HashMap<String, Rc<ast::Command>>,
+    /// One frame per function call being run, holding what each of its local
+    /// variables hid so it can be put back when the call returns.
+    locals: Vec<Vec<(String, Option<Variable>)>>,
+    /// Set while unwinding for `return` and the like.
+    flow: Option<Flow>,
+    /// Whether the shell reads commands from a user rather than a script.
+    interactive: bool,
+    /// Set by an expansion error that ends a non-interactive shell, like
+    /// `${name?}` with `name` unset.
+    fatal_error: bool,
+    /// The script and line of the command being run, to point error
+    /// messages at.
+    location: Option<(String, usize)>,
+    /// Set when a foreground job was stopped or killed with Ctrl-C, which
+    /// abandons everything being run back to the prompt.
+    interrupted: bool,
+    /// How many loops are running, which bounds `break` and `continue`.
+    loops: usize,
+    /// How many scripts are being run with `source`, which `return` can leave.
+    sources: usize,
+    /// The lines entered interactively.
+    history: History,
+    /// Aliases by name, each with the text it stands for.
+    aliases: BTreeMap<String, String>,
+    /// Programs already looked up in `PATH`, forgotten whenever it changes.
+    hashed: BTreeMap<String, Hashed>,
+    /// Options set with `shopt`.
+    shopt: BTreeMap<&'static str, bool>,
+    /// Options set with `set -o`.
+    options: BTreeMap<&'static str, bool>,
+    /// Status of the last command substitution while expanding a command,
+    /// which becomes the status of a command made only of assignments.
+    substitution_status: Option<i32>,
+}
+
+impl Shell {
+    pub fn new() -> Self {
+        Shell {
+            vars: std::env::vars()
+                .map(|(name, value)| {
+                    let var = Variable {
+                        value: Some(value),
+                        exported: true,
+                    };
+                    (name, var)
+                })
+                .collect(),
+            name: std::env::args().next().unwrap_or_default(),
+            positional: Vec::new(),
+            pid: std::process::id(),
+            last_status: 0,
+            pipestatus: vec![0],
+            last_background: None,
+            jobs: Jobs::new(),
+            functions: HashMap::new(),
+            locals: Vec::new(),
+            flow: None,
+            interrupted: false,
+            location: None,
+            interactive: false,
+            fatal_error: false,
+            loops: 0,
+            sources: 0,
+            aliases: BTreeMap::new(),
+            history: History::new(),
+            hashed: BTreeMap::new(),
+            shopt: [
+                "dotglob",
+                "failglob",
+                "globstar",
+                "histimmediate",
+                "nullglob",
+            ]
+            .into_iter()
+            .map(|name| (name, false))
+            .collect(),
+            options: [("noclobber", false)].into_iter().collect(),
+            substitution_status: None,
+        }
+    }
+
+    /// Exit status of the most recent foreground pipeline, i.e. `$?`.
+    pub fn last_status(&self) -> i32 {
+        self.last_status
+    }
+
+    /// Records the statuses of a finished pipeline; its last stage becomes `$?`.
+    pub fn set_status(&mut self, statuses: Vec<i32>) {
+        if let Some(&last) = statuses.last() {
+            self.last_status = last;
+            self.pipestatus = statuses;
+        }
+    }
+
+    /// `$0`, used to prefix errors in scripts.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sets `$0`, which is the script being run if there is one.
+    pub fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    pub fn positional(&self) -> &[String] {
+        &self.positional
+    }
+
+    pub fn set_positional(&mut self, positional: Vec<String>) {
+        self.positional = positional;
+    }
+
+    /// Looks up a variable or a special parameter such as `$?` or `$1`.
+    pub fn get_var(&self, name: &str) -> Option<String> {
+        match name {
+            "?" => Some(self.last_status.to_string()),
+            "$" => Some(self.pid.to_string()),
+            "!" => self.last_background.map(|pid| pid.to_string()),
+            "#" => Some(self.positional.len().to_string()),
+            "PIPESTATUS" => Some(self.pipestatus[0].to_string()),
+            _ => match name.parse::<usize>() {
+                // `${00}` is `$0` too.
+                Ok(0) => Some(self.name.clone()),
+                Ok(n) => self.positional.get(n - 1).cloned(),
+                Err(_) => self.vars.get(name).and_then(|var| var.value.clone()),
+            },
+        }
+    }
+
+    /// Assigns a shell variable, updating the environment if it is exported.
+    pub fn set_var(&mut self, name: &str, value: String) {
+        let var = self.vars.entry(name.to_string()).or_insert(Variable {
+            value: None,
+            exported: false,
+        });
+        if var.exported {
+            std::env::set_var(name, &value);
+        }
+        var.value = Some(value);
+        self.changed(name);
+    }
+
+    /// Marks a variable as exported, assigning it first if `value` is given.
+    pub fn export(&mut self, name: &str, value: Option<String>) {
+        let var = self.vars.entry(name.to_string()).or_insert(Variable {
+            value: None,
+            exported: false,
+        });
+        var.exported = true;
+        if value.is_some() {
+            var.value = value;
+        }
+        if let Some(value) = &var.value {
+            std::env::set_var(name, value);
+        }
+        self.changed(name);
+    }
+
+    /// Removes the export attribute from a variable, keeping its value.
+    pub fn unexport(&mut self, name: &str) {
+        if let Some(var) = self.vars.get_mut(name) {
+            var.exported = false;
+            std::env::remove_var(name);
+        }
+    }
+
+    pub fn unset_var(&mut self, name: &str) {
+        if self.vars.remove(name).is_some_and(|var| var.exported) {
+            std::env::remove_var(name);
+        }
+        self.changed(name);
+    }
+
+    /// Makes a variable local to the function being run: it hides any
+    /// variable of the same name until the function returns, also from the
+    /// functions it calls.
+    pub fn make_local(&mut self, name: &str, value: Option<String>) -> Result<(), String> {
+        let Some(frame) = self.locals.last_mut() else {
+            return Err("can only be used in a function".into());
+        };
+        if !frame.iter().any(|(local, _)| local == name) {
+            let hidden = self.vars.remove(name);
+            if hidden.as_ref().is_some_and(|var| var.exported) {
+                std::env::remove_var(name);
+            }
+            frame.push((name.to_string(), hidden));
+        }
+        match value {
+            Some(value) => self.set_var(name, value),
+            None => self.changed(name),
+        }
+        Ok(())
+    }
+
+    /// The local variables of the function being run, by name.
+    pub fn locals(&self) -> Vec<(&str, Option<&str>)> {
+        let mut names: Vec<&str> = self
+            .locals
+            .last()
+            .map(|frame| frame.iter().map(|(name, _)| name.as_str()).collect())
+            .unwrap_or_default();
+        names.sort_unstable();
+        names
+            .into_iter()
+            .map(|name| {
+                let value = self.vars.get(name).and_then(|var| var.value.as_deref());
+                (name, value)
+            })
+            .collect()
+    }
+
+    /// Puts back a variable hidden by a local one.
+    fn restore_var(&mut self, name: &str, hidden: Option<Variable>) {
+        if self.vars.remove(name).is_some_and(|var| var.exported) {
+            std::env::remove_var(name);
+        }
+        if let Some(var) = hidden {
+            if let (true, Some(value)) = (var.exported, &var.value) {
+                std::env::set_var(name, value);
+            }
+            self.vars.insert(name.to_string(), var);
+        }
+        self.changed(name);
+    }
+
+    /// Drops state derived from a variable that was just modified.
+    fn changed(&mut self, name: &str) {
+        if name == "PATH" {
+            self.hashed.clear();
+        }
+    }
+
+    /// All variables, sorted by name.
+    pub fn variables(&self) -> Vec<(&str, &Variable)> {
+        let mut vars: Vec<_> = self
+            .vars
+            .iter()
+            .map(|(name, var)| (name.as_str(), var))
+            .collect();
+        vars.sort_by_key(|(name, _)| *name);
+        vars
+    }
+
+    /// All exported variables, sorted by name.
+    pub fn exported(&self) -> Vec<(&str, &Variable)> {
+        let mut vars = self.variables();
+        vars.retain(|(_, var)| var.exported);
+        vars
+    }
+
+    /// Whether the `shopt` option `name` is enabled.
+    pub fn shopt(&self, name: &str) -> bool {
+        self.shopt.get(name).copied().unwrap_or(false)
+    }
+
+    /// Enables or disables a `shopt` option, returning `false` if there is no
+    /// such option.
+    pub fn set_shopt(&mut self, name: &str, enabled: bool) -> bool {
+        match self.shopt.get_mut(name) {
+            Some(option) => {
+                *option = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Every `shopt` option with its current setting, sorted by name.
+    pub fn shopts(&self) -> impl Iterator<Item = (&'static str, bool)> + '_ {
+        self.shopt.iter().map(|(name, enabled)| (*name, *enabled))
+    }
+
+    pub fn function(&self, name: &str) -> Option<Rc<ast::Command>> {
+        self.functions.get(name).cloned()
+    }
+
+    pub fn unset_function(&mut self, name: &str) {
+        self.functions.remove(name);
+    }
+
+    /// Whether a function is being run, so that `return` can leave it.
+    pub fn in_function(&self) -> bool {
+        !self.locals.is_empty()
+    }
+
+    /// Whether there is a function or sourced script for `return` to leave.
+    pub fn can_return(&self) -> bool {
+        self.in_function() || self.sources > 0
+    }
+
+    pub fn set_flow(&mut self, flow: Flow) {
+        self.flow = Some(flow);
+    }
+
+    /// Whether the commands being run are being left early, for a change of
+    /// control flow or an interrupt.
+    fn unwinding(&self) -> bool {
+        self.flow.is_some() || self.interrupted
+    }
+
+    /// What error messages start with: the script and line being run, if
+    /// any.
+    pub fn error_prefix(&self) -> String {
+        match &self.location {
+            Some((source, line)) => format!("{}: line {}: ", source, line),
+            None => String::new(),
+        }
+    }
+
+    /// Prints an error message, pointing at the script and line being run.
+    pub fn report(&self, message: impl std::fmt::Display) {
+        eprintln!("{}{}", self.error_prefix(), message);
+    }
+
+    pub fn set_interactive(&mut self) {
+        self.interactive = true;
+    }
+
+    /// Makes the expansion error being reported end the shell unless it is
+    /// interactive.
+    pub fn set_fatal_error(&mut self) {
+        self.fatal_error = true;
+    }
+
+    /// Forgets a `break` or `continue` left with no loop to take it.
+    pub fn clear_stray_flow(&mut self) {
+        if self.loops == 0 && matches!(self.flow, Some(Flow::Break(_) | Flow::Continue(_))) {
+            self.flow = None;
+        }
+    }
+
+    /// Forgets an interrupt once it has reached the prompt.
+    pub fn clear_interrupt(&mut self) {
+        self.interrupted = false;
+    }
+
+    /// Waits for a job in the foreground. Under job control, a job that is
+    /// stopped or killed with Ctrl-C interrupts the commands around it, like
+    /// a loop it is part of.
+    pub fn wait_foreground(&mut self, job: Job) -> io::Result<Vec<i32>> {
+        let statuses = self.jobs.wait_foreground(job)?;
+        let interrupts = [128 + libc::SIGINT, 128 + libc::SIGTSTP];
+        if self.jobs.enabled() && statuses.iter().any(|status| interrupts.contains(status)) {
+            self.interrupted = true;
+        }
+        Ok(statuses)
+    }
+
+    /// The number of loops being run, around the current command.
+    pub fn loops(&self) -> usize {
+        self.loops
+    }
+
+    /// Runs a function body with `args` as its name and positional
+    /// parameters, returning its exit status.
+    pub fn call_function(&mut self, body: &ast::Command, args: Vec<String>) -> io::Result<i32> {
+        let positional = std::mem::replace(&mut self.positional, args[1..].to_vec());
+        self.locals.push(Vec::new());
+        let result = self.run_command(body);
+
+        let frame = self.locals.pop().unwrap_or_default();
+        for (name, hidden) in frame.into_iter().rev() {
+            self.restore_var(&name, hidden);
+        }
+        self.positional = positional;
+        if self.flow == Some(Flow::Return) {
+            self.flow = None;
+        }
+        result.map(|()| self.last_status)
+    }
+
+    /// Runs the text of a script in the current shell, as `source` does. If
+    /// there are `args`, they are the positional parameters until it ends.
+    pub fn source(&mut self, path: &str, text: &str, args: Vec<String>) -> io::Result<i32> {
+        let positional = if args.is_empty() {
+            None
+        } else {
+            Some(std::mem::replace(&mut self.positional, args))
+        };
+        self.sources += 1;
+        let result = self.run_script(Script::from_text(text), path);
+
+        self.sources -= 1;
+        if let Some(positional) = positional {
+            self.positional = positional;
+        }
+        if self.flow == Some(Flow::Return) {
+            self.flow = None;
+        }
+        result.map(|_| self.last_status)
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// Reads the history file, which also makes the shell write it back.
+    pub fn load_history(&mut self) {
+        let settings = history::Settings::new(self);
+        self.history.load(&settings);
+    }
+
+    /// Records an input line in the history, returning whether it was kept.
+    /// With `shopt -s histimmediate` it is written to the file at once.
+    pub fn add_history(&mut self, line: &str) -> bool {
+        let settings = history::Settings::new(self);
+        let added = self.history.add(line, &settings);
+        if added && self.shopt("histimmediate") {
+            self.save_history();
+        }
+        added
+    }
+
+    /// Appends the new history entries to the history file.
+    pub fn save_history(&mut self) {
+        // Forked subshells share the entries but must not write them again.
+        if std::process::id() != self.pid {
+            return;
+        }
+        let settings = history::Settings::new(self);
+        if let Err(e) = self.history.save(&settings) {
+            let file = self.get_var("HISTFILE").unwrap_or_default();
+            eprintln!("history: {}: {}", file, error_text(&e));
+        }
+    }
+
+    pub fn alias(&self, name: &str) -> Option<&str> {
+        self.aliases.get(name).map(String::as_str)
+    }
+
+    /// Every alias, sorted by name.
+    pub fn aliases(&self) -> &BTreeMap<String, String> {
+        &self.aliases
+    }
+
+    pub fn set_alias(&mut self, name: &str, value: String) {
+        self.aliases.insert(name.to_string(), value);
+    }
+
+    /// Removes an alias, returning whether there was one.
+    pub fn unalias(&mut self, name: &str) -> bool {
+        self.aliases.remove(name).is_some()
+    }
+
+    pub fn clear_aliases(&mut self) {
+        self.aliases.clear();
+    }
+
+    /// Finds a program in `PATH`, going through the hash table so each name
+    /// is only searched for once.
+    pub fn find_program(&mut self, name: &str) -> Option<std::path::PathBuf> {
+        if let Some(hashed) = self.hashed.get(name).filter(|h| h.path.is_file()) {
+            return Some(hashed.path.clone());
+        }
+        let path = crate::command::find_in_path(name)?;
+        self.hash(name, path.clone());
+        Some(path)
+    }
+
+    pub fn hashed(&self, name: &str) -> Option<&Hashed> {
+        self.hashed.get(name)
+    }
+
+    /// The hash table, sorted by name.
+    pub fn hashed_programs(&self) -> impl Iterator<Item = (&str, &Hashed)> + '_ {
+        self.hashed
+            .iter()
+            .map(|(name, hashed)| (name.as_str(), hashed))
+    }
+
+    /// Remembers `path` as the location of `name`.
+    pub fn hash(&mut self, name: &str, path: std::path::PathBuf) {
+        self.hashed
+            .insert(name.to_string(), Hashed { path, hits: 0 });
+    }
+
+    pub fn unhash(&mut self, name: &str) -> bool {
+        self.hashed.remove(name).is_some()
+    }
+
+    pub fn clear_hash(&mut self) {
+        self.hashed.clear();
+    }
+
+    /// Counts a run of a program found through the hash table.
+    pub fn hit(&mut self, name: &str) {
+        if let Some(hashed) = self.hashed.get_mut(name) {
+            hashed.hits += 1;
+        }
+    }
+
+    pub fn jobs(&self) -> &Jobs {
+        &self.jobs
+    }
+
+    pub fn jobs_mut(&mut self) -> &mut Jobs {
+        &mut self.jobs
+    }
+
+    /// Whether the `set -o` option `name` is enabled.
+    pub fn option(&self, name: &str) -> bool {
+        self.options.get(name).copied().unwrap_or(false)
+    }
+
+    /// Enables or disables a `set -o` option, returning `false` if there is no
+    /// such option.
+    pub fn set_option(&mut self, name: &str, enabled: bool) -> bool {
+        match self.options.get_mut(name) {
+            Some(option) => {
+                *option = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Every `set -o` option with its current setting, sorted by name.
+    pub fn options(&self) -> impl Iterator<Item = (&'static str, bool)> + '_ {
+        self.options.iter().map(|(name, enabled)| (*name, *enabled))
+    }
+
+    /// Runs `program` in a subshell and returns what it wrote to stdout, for
+    /// `$(...)`. Its exit status becomes `$?`.
+    pub fn substitute(&mut self, program: &str) -> Result<String, String> {
+        let (mut reader, writer) = os_pipe::pipe().map_err(|e| error_text(&e))?;
+        let pid = pipeline::fork(
+            [None, Some(writer.into()), None],
+            Some(reader.as_raw_fd()),
+            || {
+                self.jobs.disable();
+                match Parser::parse(program, &self.aliases) {
+                    Ok(list) => match self.run_list(&list) {
+                        Ok(()) => self.last_status,
+                        Err(_) => 1,
+                    },
+                    Err(e) => {
+                        self.report(e);
+                        2
+                    }
+                }
+            },
+        )
+        .map_err(|e| error_text(&e))?;
+
+        let mut output = Vec::new();
+        let read = reader.read_to_end(&mut output);
+        let status = pipeline::wait_pid(pid).map_err(|e| error_text(&e))?;
+        read.map_err(|e| error_text(&e))?;
+
+        self.substitution_status = Some(status);
+        self.set_status(vec![status]);
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+
+    /// Looks up an array variable; `PIPESTATUS` holds the exit status of every
+    /// stage of the last pipeline, and any other variable is a one-element
+    /// array.
+    pub fn get_array(&self, name: &str) -> Vec<String> {
+        match name {
+            "PIPESTATUS" => self.pipestatus.iter().map(i32::to_string).collect(),
+            name => self.get_var(name).into_iter().collect(),
+        }
+    }
+}
+
+impl Shell {
+    /// Runs every and-or list of `list` in order.
+    pub fn run_list(&mut self, list: &List) -> io::Result<()> {
+        for and_or in &list.0 {
+            self.run_and_or(and_or)?;
+            if self.unwinding() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs a script a complete command at a time, so that each command is
+    /// parsed only after the ones before it have run. A syntax error is
+    /// reported against `source` and the line its command started on, and ends
+    /// the script early with `false` and a status of 2.
+    pub fn run_script(
+        &mut self,
+        lines: impl Iterator<Item = io::Result<String>>,
+        source: &str,
+    ) -> io::Result<bool> {
+        let mut input = String::new();
+        let mut start = 1;
+        for (number, line) in (1..).zip(lines) {
+            if input.is_empty() {
+                start = number;
+            }
+            input.push_str(&line?);
+            input.push('\n');
+            if !Parser::is_complete(&input) {
+                continue;
+            }
+            let command = std::mem::take(&mut input);
+            if !self.run_script_input(&command, source, start)? {
+                return Ok(false);
+            }
+            if self.unwinding() {
+                return Ok(true);
+            }
+        }
+        if input.is_empty() {
+            return Ok(true);
+        }
+        self.run_script_input(&input, source, start)
+    }
+
+    fn run_script_input(&mut self, input: &str, source: &str, line: usize) -> io::Result<bool> {
+        match Parser::parse(input, &self.aliases) {
+            Ok(list) => {
+                let outer = self.location.replace((source.to_string(), line));
+                let result = self.run_list(&list);
+                self.location = outer;
+                result?;
+                self.clear_stray_flow();
+            }
+            Err(e) => {
+                eprintln!("{}: line {}: {}", source, line, e);
+                self.set_status(vec![2]);
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn run_and_or(&mut self, and_or: &AndOr) -> io::Result<()> {
+        if and_or.background {
+            return self.run_background(and_or);
+        }
+        self.run_connected(and_or)
+    }
+
+    /// Runs the pipelines of an and-or list in the foreground.
+    fn run_connected(&mut self, and_or: &AndOr) -> io::Result<()> {
+        self.run_pipeline(&and_or.first)?;
+        for (connector, pipeline) in &and_or.rest {
+            if self.unwinding() {
+                break;
+            }
+            let succeeded = self.last_status == 0;
+            if succeeded == (*connector == Connector::And) {
+                self.run_pipeline(pipeline)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts an and-or list as a background job. A lone pipeline is the job
+    /// itself; anything longer runs in a forked subshell.
+    fn run_background(&mut self, and_or: &AndOr) -> io::Result<()> {
+        let command = and_or.to_string();
+        let job = if and_or.rest.is_empty() {
+            let stages = match self.expand_pipeline(&and_or.first) {
+                Ok(stages) => stages,
+                Err(e) => return self.expansion_failed(e),
+            };
+            pipeline::spawn(stages, command, false, self)?
+        } else {
+            let group = self.jobs.group(0, false);
+            let stdin = match group {
+                Some(_) => None,
+                None => Some(std::fs::File::open("/dev/null")?.into()),
+            };
+            let pid = pipeline::fork([stdin, None, None], None, || {
+                let _ = job::prepare_child(group);
+                self.jobs.disable();
+                match self.run_connected(and_or) {
+                    Ok(()) => self.last_status,
+                    Err(_) => 1,
+                }
+            })?;
+            let pgid = self.jobs.join(pid, group);
+            Job::new(pgid, vec![Process::started(pid)], command)
+        };
+
+        let pid = job.last_pid();
+        self.last_background = Some(pid);
+        let id = self.jobs.add(job);
+        if self.jobs.enabled() {
+            eprintln!("[{}] {}", id, pid);
+        }
+        self.set_status(vec![0]);
+        Ok(())
+    }
+
+    fn run_pipeline(&mut self, pipeline: &Pipeline) -> io::Result<()> {
+        if let [command] = &pipeline.commands[..] {
+            return self.run_command(command);
+        }
+
+        let stages = match self.expand_pipeline(pipeline) {
+            Ok(stages) => stages,
+            Err(e) => return self.expansion_failed(e),
+        };
+        let statuses = pipeline::run(stages, pipeline.to_string(), self)?;
+        self.set_status(statuses);
+        Ok(())
+    }
+
+    /// Runs a command that is a pipeline on its own, in the shell itself
+    /// unless it is an external program or a subshell.
+    fn run_command(&mut self, command: &ast::Command) -> io::Result<()> {
+        self.substitution_status = None;
+        match command {
+            ast::Command::Function(name, body) => {
+                self.functions.insert(name.clone(), body.clone());
+                self.set_status(vec![0]);
+                return Ok(());
+            }
+            ast::Command::Compound(compound, redirects)
+                if !matches!(**compound, CompoundCommand::Subshell(_)) =>
+            {
+                let redirections = match self.open_redirects(redirects) {
+                    Ok(redirections) => redirections,
+                    Err(e) => return self.expansion_failed(e),
+                };
+                let _saved = match redirections.apply_in_shell() {
+                    Ok(saved) => saved,
+                    Err(e) => return self.expansion_failed(error_text(&e)),
+                };
+                return self.run_compound(compound);
+            }
+            // Bare assignments are expanded and made one at a time, so that a
+            // value can use the ones before it.
+            ast::Command::Simple(simple) if simple.words.is_empty() => {
+                for (name, value) in &simple.assignments {
+                    match expand::expand_assignment(value, self) {
+                        Ok(value) => self.set_var(name, value),
+                        Err(e) => return self.expansion_failed(e),
+                    }
+                }
+                let redirections = match self.open_redirects(&simple.redirects) {
+                    Ok(redirections) => redirections,
+                    Err(e) => return self.expansion_failed(e),
+                };
+                if let Err(e) = redirections.apply_in_shell() {
+                    return self.expansion_failed(error_text(&e));
+                }
+                self.set_status(vec![self.substitution_status.unwrap_or(0)]);
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let stage = match self.expand_command(command) {
+            Ok(stage) => stage,
+            Err(e) => return self.expansion_failed(e),
+        };
+
+        // External programs and subshells run as a job, which can be stopped
+        // and resumed.
+        let external = stage.compound.is_some()
+            || stage
+                .args
+                .first()
+                .is_some_and(|name| matches!(Command::lookup(name, self), Command::Binary(_)));
+        if external {
+            let statuses = pipeline::run(vec![stage], command.to_string(), self)?;
+            self.set_status(statuses);
+            return Ok(());
+        }
+
+        let Stage {
+            env,
+            args,
+            redirections,
+            ..
+        } = stage;
+
+        // A single command runs in the shell itself, so its redirections are
+        // applied to the shell's own descriptors until it finishes.
+        let _saved = match redirections.apply_in_shell() {
+            Ok(saved) => saved,
+            Err(e) => return self.expansion_failed(error_text(&e)),
+        };
+        // Builtins write to descriptor 1 through a buffer of their own, which
+        // is dropped if writing fails so that nothing left in it shows up in
+        // the output of a later command.
+        let stdout = ManuallyDrop::new(unsafe { std::fs::File::from_raw_fd(libc::STDOUT_FILENO) });
+        let mut out_writer: Box<dyn Write + '_> = Box::new(io::BufWriter::new(&*stdout));
+        let mut err_writer: Box<dyn Write> =
+            Box::new(Prefixed::new(io::stderr(), self.error_prefix()));
+
+        if args.is_empty() {
+            // A bare `NAME=value` sets shell variables.
+            for (name, value) in env {
+                self.set_var(&name, value);
+            }
+            self.set_status(vec![self.substitution_status.unwrap_or(0)]);
+            return Ok(());
+        }
+
+        // A builtin that fails to write, e.g. to a full disk, only fails
+        // itself.
+        let name = args[0].clone();
+        let status = match Command::lookup(&name, self)
+            .with_env(env)
+            .run(args, &mut out_writer, &mut err_writer, self)
+            .and_then(|status| out_writer.flush().map(|()| status))
+        {
+            Ok(status) => status,
+            Err(e) => {
+                self.report(format_args!("{}: write error: {}", name, error_text(&e)));
+                1
+            }
+        };
+        self.set_status(vec![status]);
+        Ok(())
+    }
+
+    /// Runs the body of a compound command. A subshell only gets here once it
+    /// has been forked.
+    pub fn run_compound(&mut self, compound: &CompoundCommand) -> io::Result<()> {
+        match compound {
+            CompoundCommand::Group(list) | CompoundCommand::Subshell(list) => self.run_list(list),
+            CompoundCommand::If {
+                branches,
+                otherwise,
+            } => {
+                for (condition, body) in branches {
+                    self.run_list(condition)?;
+                    if self.unwinding() {
+                        return Ok(());
+                    }
+                    if self.last_status == 0 {
+                        return self.run_list(body);
+                    }
+                }
+                match otherwise {
+                    Some(otherwise) => self.run_list(otherwise),
+                    None => {
+                        self.set_status(vec![0]);
+                        Ok(())
+                    }
+                }
+            }
+            CompoundCommand::Loop {
+                until,
+                condition,
+                body,
+            } => {
+                let mut status = 0;
+                self.run_loop(|shell| {
+                    shell.run_list(condition)?;
+                    let more = (shell.last_status == 0) != *until;
+                    // `continue` in the condition counts as its status, as
+                    // in other shells.
+                    if matches!(shell.flow, Some(Flow::Continue(_))) && !shell.interrupted {
+                        return Ok(more);
+                    }
+                    if shell.unwinding() || !more {
+                        return Ok(false);
+                    }
+                    shell.run_list(body)?;
+                    status = shell.last_status;
+                    Ok(true)
+                })?;
+                if self.flow != Some(Flow::Return) {
+                    self.set_status(vec![status]);
+                }
+                Ok(())
+            }
+            CompoundCommand::For { name, words, body } => {
+                let items = match words {
+                    Some(words) => match expand::expand_words(words, self) {
+                        Ok(items) => items,
+                        Err(e) => return self.expansion_failed(e),
+                    },
+                    None => self.positional.clone(),
+                };
+                self.set_status(vec![0]);
+                let mut items = items.into_iter();
+                self.run_loop(|shell| {
+                    let Some(item) = items.next() else {
+                        return Ok(false);
+                    };
+                    shell.set_var(name, item);
+                    shell.run_list(body)?;
+                    Ok(true)
+                })
+            }
+            CompoundCommand::ArithmeticFor {
+                init,
+                condition,
+                step,
+                body,
+            } => {
+                if let Err(e) = arith::evaluate(init, self) {
+                    return self.expansion_failed(e);
+                }
+                self.set_status(vec![0]);
+                let mut first = true;
+                self.run_loop(|shell| {
+                    if !std::mem::take(&mut first) {
+                        arith::evaluate(step, shell).map_err(io::Error::other)?;
+                    }
+                    // An empty condition is always true.
+                    if !condition.is_empty()
+                        && arith::evaluate(condition, shell).map_err(io::Error::other)? == 0
+                    {
+                        return Ok(false);
+                    }
+                    shell.run_list(body)?;
+                    Ok(true)
+                })
+                .or_else(|e| self.expansion_failed(error_text(&e)))
+            }
+            CompoundCommand::Case { word, items } => {
+                let word = match expand::expand_assignment(word, self) {
+                    Ok(word) => word,
+                    Err(e) => return self.expansion_failed(e),
+                };
+                for item in items {
+                    for pattern in &item.patterns {
+                        match expand::matches_pattern(&word, pattern, self) {
+                            Ok(true) => return self.run_list(&item.body),
+                            Ok(false) => {}
+                            Err(e) => return self.expansion_failed(e),
+                        }
+                    }
+                }
+                self.set_status(vec![0]);
+                Ok(())
+            }
+        }
+    }
+
+    /// Runs `iteration` until it returns false, handling `break` and
+    /// `continue` for the loop.
+    fn run_loop(
+        &mut self,
+        mut iteration: impl FnMut(&mut Self) -> io::Result<bool>,
+    ) -> io::Result<()> {
+        self.loops += 1;
+        let result = loop {
+            // `break` or `continue` in a condition stops the iteration too,
+            // so the flow is looked at before whether to go on.
+            let more = match iteration(self) {
+                Ok(more) => more,
+                Err(e) => break Err(e),
+            };
+            if self.interrupted {
+                break Ok(());
+            }
+            match self.flow {
+                Some(Flow::Break(n)) => {
+                    self.flow = (n > 1).then(|| Flow::Break(n - 1));
+                    break Ok(());
+                }
+                Some(Flow::Continue(n)) if n > 1 => {
+                    self.flow = Some(Flow::Continue(n - 1));
+                    break Ok(());
+                }
+                Some(Flow::Continue(_)) => self.flow = None,
+                Some(Flow::Return) => break Ok(()),
+                None => {}
+            }
+            if !more {
+                break Ok(());
+            }
+        };
+        self.loops -= 1;
+        result
+    }
+
+    fn expand_pipeline(&mut self, pipeline: &Pipeline) -> Result<Vec<Stage>, String> {
+        self.substitution_status = None;
+        pipeline
+            .commands
+            .iter()
+            .map(|command| self.expand_command(command))
+            .collect()
+    }
+
+    fn expand_command(&mut self, command: &ast::Command) -> Result<Stage, String> {
+        let command = match command {
+            ast::Command::Simple(command) => command,
+            ast::Command::Compound(compound, redirects) => {
+                return Ok(Stage {
+                    env: Vec::new(),
+                    args: Vec::new(),
+                    redirections: self.open_redirects(redirects)?,
+                    compound: Some(compound.clone()),
+                });
+            }
+            // Defined in a forked stage, the function would be lost at once.
+            ast::Command::Function(..) => {
+                return Ok(Stage {
+                    env: Vec::new(),
+                    args: Vec::new(),
+                    redirections: Redirections::default(),
+                    compound: None,
+                })
+            }
+        };
+
+        let mut env = Vec::with_capacity(command.assignments.len());
+        for (name, value) in &command.assignments {
+            env.push((name.clone(), expand::expand_assignment(value, self)?));
+        }
+        let args = expand::expand_words(&command.words, self)?;
+        let redirections = self.open_redirects(&command.redirects)?;
+        Ok(Stage {
+            env,
+            args,
+            redirections,
+            compound: None,
+        })
+    }
+
+    /// Expands the targets of a command's redirections and opens their files.
+    fn open_redirects(&mut self, redirects: &[Redirect]) -> Result<Redirections, String> {
+        let mut redirections = Redirections::default();
+        for Redirect { fd, op, target, .. } in redirects {
+            let word = match op {
+                RedirectOp::HereDoc => expand::expand_heredoc(target, self)?,
+                RedirectOp::LiteralHereDoc => target.clone(),
+                RedirectOp::HereString => expand::expand_assignment(target, self)? + "\n",
+                _ => expand::expand_word(target, self)?,
+            };
+            match op {
+                RedirectOp::HereDoc | RedirectOp::LiteralHereDoc | RedirectOp::HereString => {
+                    let file = here_document(&word).map_err(|e| error_text(&e))?;
+                    redirections.open(*fd, file).map_err(|e| error_text(&e))?;
+                }
+                RedirectOp::DuplicateInput | RedirectOp::DuplicateOutput if word == "-" => {
+                    redirections.close(*fd);
+                }
+                RedirectOp::DuplicateInput | RedirectOp::DuplicateOutput => {
+                    match word.parse() {
+                        Ok(source) => redirections
+                            .duplicate(*fd, source)
+                            .map_err(|_| format!("{}: Bad file descriptor", word))?,
+                        // `>&file` is another way to write `&>file`.
+                        Err(_) if *op == RedirectOp::DuplicateOutput && *fd == 1 => {
+                            let file = self.open_file(&word, RedirectOp::Output)?;
+                            redirections.open(1, file).map_err(|e| error_text(&e))?;
+                            redirections.duplicate(2, 1).map_err(|e| error_text(&e))?;
+                        }
+                        Err(_) => return Err(format!("{}: ambiguous redirect", target)),
+                    }
+                }
+                op => {
+                    let file = self.open_file(&word, *op)?;
+                    redirections.open(*fd, file).map_err(|e| error_text(&e))?;
+                }
+            }
+        }
+        Ok(redirections)
+    }
+
+    /// Opens the file a redirection points at, honouring `noclobber`.
+    fn open_file(&self, path: &str, op: RedirectOp) -> Result<std::fs::File, String> {
+        let mut options = std::fs::OpenOptions::new();
+        match op {
+            RedirectOp::Input => options.read(true),
+            RedirectOp::ReadWrite => options.read(true).write(true).create(true),
+            RedirectOp::Append => options.append(true).create(true),
+            _ => {
+                // Only regular files are protected, so `>/dev/null` still works.
+                let exists = std::fs::metadata(path).is_ok_and(|m| m.is_file());
+                if op == RedirectOp::Output && self.option("noclobber") && exists {
+                    return Err(format!("{}: cannot overwrite existing file", path));
+                }
+                options.write(true).create(true).truncate(true)
+            }
+        };
+        options
+            .open(path)
+            .map_err(|e| format!("{}: {}", path, error_text(&e)))
+    }
+
+    /// Reports an error that prevented a pipeline from running.
+    fn expansion_failed(&mut self, e: String) -> io::Result<()> {
+        self.report(e);
+        // Subshells are never interactive, even when forked from a shell
+        // that is.
+        let interactive = self.interactive && std::process::id() == self.pid;
+        if std::mem::take(&mut self.fatal_error) && !interactive {
+            std::process::exit(1);
+        }
+        self.set_status(vec![1]);
+        Ok(())
+    }
+}
+
+/// Stores the text fed to a command by a here-document or here-string in an
+/// unlinked temporary file, so that it can be read however long it is.
+fn here_document(text: &str) -> io::Result<std::fs::File> {
+    use std::io::Seek;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let dir = std::env::temp_dir();
+    let mut attempt = 0;
+    let (path, mut file) = loop {
+        let path = dir.join(format!("shell-heredoc-{}-{}", std::process::id(), attempt));
+        match std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+        {
+            Ok(file) => break (path, file),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => attempt += 1,
+            Err(e) => return Err(e),
+        }
+    };
+    std::fs::remove_file(&path)?;
+    file.write_all(text.as_bytes())?;
+    file.rewind()?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn all_zero_names_are_the_shell_name() {
+        let mut shell = Shell::new();
+        shell.set_name("script".into());
+        shell.set_positional(vec!["first".into()]);
+        for name in ["0", "00", "000"] {
+            assert_eq!(shell.get_var(name).as_deref(), Some("script"));
+        }
+        assert_eq!(shell.get_var("01").as_deref(), Some("first"));
+        assert_eq!(shell.get_var("2"), None);
+    }
+
+    fn run(shell: &mut Shell, text: &str) {
+        shell.run_script(Script::from_text(text), "test").unwrap();
+    }
+
+    #[test]
+    fn break_in_a_loop_condition_leaves_the_loop() {
+        let mut shell = Shell::new();
+        run(&mut shell, "while break; do :; done; after=yes\n");
+        assert_eq!(shell.get_var("after").as_deref(), Some("yes"));
+    }
+
+    #[test]
+    fn continue_in_a_loop_condition_tests_it_again() {
+        let mut shell = Shell::new();
+        run(
+            &mut shell,
+            "i=0; out=\n\
+             while i=$((i+1)); [ $i -lt 3 ] && continue; [ $i -lt 5 ]; do out=$out$i; done\n",
+        );
+        assert_eq!(shell.get_var("out").as_deref(), Some("34"));
+    }
+
+    #[test]
+    fn return_in_a_loop_condition_keeps_its_status() {
+        let mut shell = Shell::new();
+        run(&mut shell, "f() { while return 3; do :; done; }; f; s=$?\n");
+        assert_eq!(shell.get_var("s").as_deref(), Some("3"));
+    }
+
+    #[test]
+    fn stray_flow_does_not_outlive_its_line() {
+        let mut shell = Shell::new();
+        let list = Parser::parse("while break; do :; done", &BTreeMap::new()).unwrap();
+        shell.run_list(&list).unwrap();
+        shell.clear_stray_flow();
+        run(&mut shell, "a=1; b=2\n");
+        assert_eq!(shell.get_var("b").as_deref(), Some("2"));
+    }
+
+    #[test]
+    fn bare_assignments_see_the_ones_before_them() {
+        let mut shell = Shell::new();
+        run(&mut shell, "a=1 b=$a c=${b}2\n");
+        assert_eq!(shell.get_var("b").as_deref(), Some("1"));
+        assert_eq!(shell.get_var("c").as_deref(), Some("12"));
+    }
+}